@@ -1,7 +1,11 @@
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use embedded_graphics_core::{
-    draw_target::DrawTarget, geometry::OriginDimensions, geometry::Point, pixelcolor::Rgb565,
-    primitives::Rectangle,
+    draw_target::DrawTarget,
+    geometry::OriginDimensions,
+    geometry::{Point, Size},
+    pixelcolor::{BinaryColor, Rgb565},
+    primitives::{PointsIter, Rectangle},
+    Pixel,
 };
 use packed_display_buffer::PackedBuffer;
 use tinybmp::Bmp;
@@ -34,7 +38,8 @@ fn mask(c: &mut Criterion) {
             BenchmarkId::from_parameter(format!("Top left {:?}", tl)),
             &pixels,
             |b, pixels| {
-                let mut buffer = PackedBuffer::<128, 64, { 128 * 64 / u8::BITS as usize }>::new();
+                let mut buffer =
+                    PackedBuffer::<128, 64, { 128 * 64 / u8::BITS as usize }, BinaryColor>::new();
 
                 b.iter(|| buffer.fill_contiguous(&bb, pixels.clone()));
             },
@@ -43,5 +48,56 @@ fn mask(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, mask);
+fn full_screen(c: &mut Criterion) {
+    let cases = [
+        // Page aligned, so every page is covered by whole rows
+        Point::new(0, 0),
+        // Shifted by part of a page
+        Point::new(0, 3),
+    ];
+
+    let size = Size::new(128, 64);
+
+    let colors = (0..size.width * size.height)
+        .map(|i| i % 3 == 0 || i % 5 == 0)
+        .map(BinaryColor::from);
+
+    let mut group = c.benchmark_group("full screen image");
+
+    for tl in cases.iter() {
+        let area = Rectangle::new(*tl, size);
+
+        group.bench_with_input(
+            BenchmarkId::new("fill_contiguous", format!("{:?}", tl)),
+            &colors,
+            |b, colors| {
+                let mut buffer =
+                    PackedBuffer::<128, 64, { 128 * 64 / u8::BITS as usize }, BinaryColor>::new();
+
+                b.iter(|| buffer.fill_contiguous(&area, colors.clone()));
+            },
+        );
+
+        // Baseline comparison drawing the same image pixel by pixel
+        group.bench_with_input(
+            BenchmarkId::new("draw_iter", format!("{:?}", tl)),
+            &colors,
+            |b, colors| {
+                let mut buffer =
+                    PackedBuffer::<128, 64, { 128 * 64 / u8::BITS as usize }, BinaryColor>::new();
+
+                b.iter(|| {
+                    buffer.draw_iter(
+                        area.points()
+                            .zip(colors.clone())
+                            .map(|(point, color)| Pixel(point, color)),
+                    )
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, mask, full_screen);
 criterion_main!(benches);
@@ -2,7 +2,10 @@ use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use embedded_graphics_core::{
     draw_target::DrawTarget, geometry::Point, pixelcolor::BinaryColor, primitives::Rectangle,
 };
-use packed_display_buffer::PackedBuffer;
+use packed_display_buffer::{
+    merge::{merge_bytes, merge_words},
+    PackedBuffer, RasterOp,
+};
 
 fn mask(c: &mut Criterion) {
     let cases = [
@@ -25,7 +28,8 @@ fn mask(c: &mut Criterion) {
             BenchmarkId::from_parameter(format!("{:?} - {:?}", case.top_left, case.bottom_right())),
             case,
             |b, fill_area| {
-                let mut buffer = PackedBuffer::<128, 64, { 128 * 64 / u8::BITS as usize }>::new();
+                let mut buffer =
+                    PackedBuffer::<128, 64, { 128 * 64 / u8::BITS as usize }, BinaryColor>::new();
 
                 b.iter(|| buffer.fill_solid(fill_area, BinaryColor::On));
             },
@@ -34,5 +38,101 @@ fn mask(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, mask);
+fn merge(c: &mut Criterion) {
+    let ops = [RasterOp::Replace, RasterOp::Xor];
+
+    let mut group = c.benchmark_group("partial block merge");
+
+    // Merge the bottom half of every page of a 1bpp buffer, as done for the partial top and bottom
+    // blocks of a fill
+    for width in [128usize, 256] {
+        for op in ops {
+            let mut buffer = vec![0u8; width * 64 / u8::BITS as usize];
+
+            group.bench_function(
+                BenchmarkId::new("bytes", format!("{}x64 {:?}", width, op)),
+                |b| {
+                    b.iter(|| {
+                        buffer
+                            .chunks_exact_mut(width)
+                            .for_each(|block| merge_bytes(block, 0xff, 0b1111_0000, op))
+                    })
+                },
+            );
+
+            group.bench_function(
+                BenchmarkId::new("words", format!("{}x64 {:?}", width, op)),
+                |b| {
+                    b.iter(|| {
+                        buffer
+                            .chunks_exact_mut(width)
+                            .for_each(|block| merge_words(block, 0xff, 0b1111_0000, op))
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("fill partial blocks");
+
+    // Unaligned area spanning the whole width, so every block is masked at the top and bottom
+    let area = Rectangle::with_corners(Point::new(0, 4), Point::new(255, 11));
+
+    group.bench_function("128x64", |b| {
+        let mut buffer =
+            PackedBuffer::<128, 64, { 128 * 64 / u8::BITS as usize }, BinaryColor>::new();
+
+        b.iter(|| buffer.fill_solid(&area, BinaryColor::On));
+    });
+
+    group.bench_function("256x64", |b| {
+        let mut buffer =
+            PackedBuffer::<256, 64, { 256 * 64 / u8::BITS as usize }, BinaryColor>::new();
+
+        b.iter(|| buffer.fill_solid(&area, BinaryColor::On));
+    });
+    group.finish();
+}
+
+fn lines(c: &mut Criterion) {
+    let cases = [
+        // Full height, so every page is written whole
+        (Point::new(64, 0), 64),
+        // Starts and ends part way through a page
+        (Point::new(10, 3), 50),
+        // Short line within a single page
+        (Point::new(100, 33), 4),
+    ];
+
+    let mut group = c.benchmark_group("vline");
+
+    // Vertical grid lines across the whole display
+    group.bench_function("grid", |b| {
+        let mut buffer =
+            PackedBuffer::<128, 64, { 128 * 64 / u8::BITS as usize }, BinaryColor>::new();
+
+        b.iter(|| {
+            for x in 0..128 {
+                buffer.vline(Point::new(x, 1), 62, BinaryColor::On);
+            }
+        });
+    });
+
+    for (start, height) in cases {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{:?} x {}", start, height)),
+            &(start, height),
+            |b, &(start, height)| {
+                let mut buffer =
+                    PackedBuffer::<128, 64, { 128 * 64 / u8::BITS as usize }, BinaryColor>::new();
+
+                b.iter(|| buffer.vline(start, height, BinaryColor::On));
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, mask, merge, lines);
 criterion_main!(benches);
@@ -6,10 +6,10 @@ use embedded_graphics::{
     prelude::*,
 };
 use embedded_graphics_simulator::{OutputSettings, SimulatorDisplay, SimulatorEvent, Window};
-use packed_display_buffer::PackedBuffer;
+use packed_display_buffer::{simulator::SimulatorBridge, PackedBuffer};
 
 fn draw_stuff(
-    display: &mut PackedBuffer<256, 256, { 256 * (256 / 8) }>,
+    display: &mut PackedBuffer<256, 256, { 256 * (256 / 8) }, BinaryColor>,
     _old_center: Point,
     new_center: Point,
 ) -> Result<(), core::convert::Infallible> {
@@ -24,17 +24,19 @@ fn draw_stuff(
 }
 
 fn main() -> Result<(), std::convert::Infallible> {
-    let mut display: SimulatorDisplay<BinaryColor> = SimulatorDisplay::new(Size::new(256, 256));
+    let bridge = SimulatorBridge::new();
     let mut window = Window::new("Click to move image", &OutputSettings::default());
 
-    let mut framebuffer = PackedBuffer::<256, 256, { 256 * (256 / 8) }>::new();
+    let mut framebuffer = PackedBuffer::<256, 256, { 256 * (256 / 8) }, BinaryColor>::new();
+    let mut display: SimulatorDisplay<BinaryColor> = bridge.display(&framebuffer);
 
     let mut position = Point::new(100, 100);
 
     draw_stuff(&mut framebuffer, position, position)?;
 
     'running: loop {
-        display.draw_iter(framebuffer.pixels())?;
+        bridge.update_active_area(&framebuffer, &mut display);
+        framebuffer.clear_active_area();
 
         window.update(&display);
 
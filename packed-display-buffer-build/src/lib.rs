@@ -0,0 +1,625 @@
+//! Convert images into `packed-display-buffer`'s vertical page layout at build time.
+//!
+//! Images are decoded, converted to the chosen bit depth (optionally dithering down to 1bpp) and
+//! packed into the same layout as `PackedBuffer`. The result is written out as Rust source
+//! declaring a `const` [`PackedSlice`], which can be blitted on the device without decoding or
+//! converting any pixels.
+//!
+//! BMP files are decoded with `tinybmp`, and PBM (`P1`/`P4`) and 8-bit PGM (`P2`/`P5`) files are
+//! supported directly.
+//!
+//! In `build.rs`:
+//!
+//! ```rust,no_run
+//! use packed_display_buffer::DitherMode;
+//! use packed_display_buffer_build::Converter;
+//! use std::{env, fs, path::PathBuf};
+//!
+//! let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+//!
+//! let logo = Converter::new(1)
+//!     .dither(DitherMode::FloydSteinberg)
+//!     .load("assets/logo.bmp")
+//!     .unwrap();
+//!
+//! fs::write(out.join("logo.rs"), logo.to_rust("LOGO")).unwrap();
+//!
+//! println!("cargo:rerun-if-changed=assets/logo.bmp");
+//! ```
+//!
+//! Then in the application:
+//!
+//! ```rust,ignore
+//! include!(concat!(env!("OUT_DIR"), "/logo.rs"));
+//!
+//! display.blit(LOGO, &LOGO.bounding_box(), Point::new(10, 5));
+//! ```
+//!
+//! [`PackedSlice`]: packed_display_buffer::PackedSlice
+
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, OriginDimensions, Point, Size},
+    pixelcolor::{BinaryColor, Gray8, GrayColor, PixelColor, Rgb565, Rgb888},
+    primitives::{PointsIter, Rectangle},
+    Pixel,
+};
+use packed_display_buffer::{Dither, DitherMode};
+use std::{convert::Infallible, fmt, fmt::Write, fs, io, path::Path};
+use tinybmp::{Bmp, Bpp, RawBmp};
+
+/// Maximum image width that can be dithered with an error diffusion kernel.
+const MAX_DIFFUSION_WIDTH: usize = 1024;
+
+/// Error returned when an image can't be converted.
+#[derive(Debug)]
+pub enum Error {
+    /// The image file couldn't be read.
+    Io(io::Error),
+
+    /// The BMP file couldn't be parsed.
+    Bmp(tinybmp::ParseError),
+
+    /// The PBM or PGM file is malformed.
+    InvalidPnm,
+
+    /// The file isn't a BMP, PBM or PGM image.
+    UnsupportedFormat,
+
+    /// The image is too wide to dither with an error diffusion mode.
+    TooWide,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "failed to read image: {}", e),
+            Error::Bmp(e) => write!(f, "invalid BMP image: {:?}", e),
+            Error::InvalidPnm => f.write_str("invalid PBM or PGM image"),
+            Error::UnsupportedFormat => f.write_str("unsupported image format"),
+            Error::TooWide => write!(
+                f,
+                "images wider than {} pixels can't be dithered with error diffusion",
+                MAX_DIFFUSION_WIDTH
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Converts images to a packed bit depth.
+#[derive(Debug, Clone)]
+pub struct Converter {
+    bpp: u8,
+    dither: Option<DitherMode>,
+}
+
+impl Converter {
+    /// Create a converter producing images with the given bit depth.
+    ///
+    /// Images are converted to grayscale, so `bpp` must be 1, 2, 4 or 8, matching `BinaryColor`,
+    /// `Gray2`, `Gray4` and `Gray8`.
+    pub fn new(bpp: u8) -> Self {
+        assert!(
+            matches!(bpp, 1 | 2 | 4 | 8),
+            "Bit depth must be 1, 2, 4 or 8"
+        );
+
+        Self { bpp, dither: None }
+    }
+
+    /// Dither images when converting to 1bpp.
+    ///
+    /// Without dithering, pixels are converted to 1bpp with a 50% threshold. Dithering has no
+    /// effect on other bit depths. Images over 1024 pixels wide can only use the ordered modes;
+    /// converting them with an error diffusion mode returns [`Error::TooWide`].
+    pub fn dither(mut self, mode: DitherMode) -> Self {
+        self.dither = Some(mode);
+
+        self
+    }
+
+    /// Read and convert an image file.
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<PackedImage, Error> {
+        self.convert(&fs::read(path)?)
+    }
+
+    /// Convert an image from the contents of a BMP, PBM or PGM file.
+    pub fn convert(&self, data: &[u8]) -> Result<PackedImage, Error> {
+        let (size, pixels) = if data.starts_with(b"BM") {
+            decode_bmp(data)?
+        } else if data.starts_with(b"P") {
+            decode_pnm(data)?
+        } else {
+            return Err(Error::UnsupportedFormat);
+        };
+
+        let mut image = PackedImage::new(size, self.bpp);
+        let area = Rectangle::new(Point::zero(), size);
+
+        match (self.bpp, self.dither) {
+            (1, Some(DitherMode::FloydSteinberg | DitherMode::Atkinson))
+                if size.width as usize > MAX_DIFFUSION_WIDTH =>
+            {
+                return Err(Error::TooWide);
+            }
+            (1, Some(mode)) => {
+                Dither::<_, Gray8, MAX_DIFFUSION_WIDTH>::new(&mut image, mode)
+                    .fill_contiguous(&area, pixels)
+                    .unwrap();
+            }
+            (1, None) => {
+                for (point, luma) in area.points().zip(pixels) {
+                    image.set(point, u8::from(luma.luma() >= 0x80));
+                }
+            }
+            (bpp, _) => {
+                for (point, luma) in area.points().zip(pixels) {
+                    image.set(point, luma.luma() >> (8 - bpp));
+                }
+            }
+        }
+
+        Ok(image)
+    }
+}
+
+/// An image packed into vertical pages, as used by `PackedBuffer`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedImage {
+    size: Size,
+    bpp: u8,
+    data: Vec<u8>,
+}
+
+impl PackedImage {
+    fn new(size: Size, bpp: u8) -> Self {
+        let page_bits = size.height * u32::from(bpp);
+        let len = size.width * page_bits.div_ceil(u8::BITS);
+
+        Self {
+            size,
+            bpp,
+            data: vec![0; len as usize],
+        }
+    }
+
+    fn set(&mut self, point: Point, value: u8) {
+        let bpp = u32::from(self.bpp);
+
+        let bit_y = point.y as u32 * bpp;
+        let idx = (bit_y / u8::BITS * self.size.width) as usize + point.x as usize;
+
+        self.data[idx] |= value << (bit_y % u8::BITS);
+    }
+
+    /// Bit depth of the packed pixels.
+    pub fn bpp(&self) -> u8 {
+        self.bpp
+    }
+
+    /// The packed image data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The name of the `embedded-graphics` color type with the image's bit depth.
+    pub fn color_type(&self) -> &'static str {
+        match self.bpp {
+            1 => "BinaryColor",
+            2 => "Gray2",
+            4 => "Gray4",
+            _ => "Gray8",
+        }
+    }
+
+    /// Generate Rust source declaring the image as a `const` `PackedSlice` called `name`.
+    ///
+    /// The generated code uses fully qualified paths, so it can be included anywhere with
+    /// `include!`.
+    pub fn to_rust(&self, name: &str) -> String {
+        let mut out = String::new();
+
+        let Size { width, height } = self.size;
+
+        writeln!(
+            out,
+            "/// {}x{} {}bpp image in packed vertical page layout.",
+            width, height, self.bpp
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "pub const {}: ::packed_display_buffer::PackedSlice<'static, \
+             ::embedded_graphics_core::pixelcolor::{}> = \
+             ::packed_display_buffer::PackedSlice::new(",
+            name,
+            self.color_type()
+        )
+        .unwrap();
+        writeln!(out, "    &[").unwrap();
+
+        for chunk in self.data.chunks(16) {
+            out.push_str("       ");
+
+            for byte in chunk {
+                write!(out, " 0x{:02x},", byte).unwrap();
+            }
+
+            out.push('\n');
+        }
+
+        writeln!(out, "    ],").unwrap();
+        writeln!(out, "    {},", width).unwrap();
+        writeln!(out, "    {},", height).unwrap();
+        writeln!(out, ");").unwrap();
+
+        out
+    }
+}
+
+impl OriginDimensions for PackedImage {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+/// Only used as the output of 1bpp dithering.
+impl DrawTarget for PackedImage {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if self.bounding_box().contains(point) && color.is_on() {
+                self.set(point, 1);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn decode_bmp(data: &[u8]) -> Result<(Size, Vec<Gray8>), Error> {
+    fn pixels<C>(data: &[u8]) -> Result<(Size, Vec<Gray8>), Error>
+    where
+        C: PixelColor + From<C::Raw> + Into<Gray8>,
+    {
+        let bmp = Bmp::<C>::from_slice(data).map_err(Error::Bmp)?;
+
+        let mut pixels: Vec<_> = bmp.pixels().collect();
+
+        // Pixels aren't necessarily in row-major order
+        pixels.sort_by_key(|Pixel(point, _)| (point.y, point.x));
+
+        Ok((
+            bmp.size(),
+            pixels.into_iter().map(|Pixel(_, c)| c.into()).collect(),
+        ))
+    }
+
+    let raw = RawBmp::from_slice(data).map_err(Error::Bmp)?;
+
+    // 1 and 8bpp images always have a color table. Its entries are full RGB colors, so they're
+    // decoded as `Rgb888` and converted to luma, rather than truncated to a single channel by
+    // reading them as `BinaryColor` or `Gray8`.
+    match raw.color_bpp() {
+        Bpp::Bits16 => pixels::<Rgb565>(data),
+        _ => pixels::<Rgb888>(data),
+    }
+}
+
+/// Skip whitespace and `#` comments running to the end of the line.
+///
+/// Comments are skipped anywhere whitespace is, including between plain PBM pixels, in the same
+/// way as `PackedBuffer::from_pbm`.
+fn skip_whitespace(data: &[u8], pos: &mut usize) {
+    while let Some(byte) = data.get(*pos) {
+        if *byte == b'#' {
+            while data.get(*pos).is_some_and(|b| *b != b'\n') {
+                *pos += 1;
+            }
+        } else if !byte.is_ascii_whitespace() {
+            break;
+        }
+
+        *pos += 1;
+    }
+}
+
+/// Decode a PBM (`P1`/`P4`) or PGM (`P2`/`P5`) image to luma values.
+///
+/// Only 8-bit PGM images are supported, i.e. with a maximum value of at most 255.
+fn decode_pnm(data: &[u8]) -> Result<(Size, Vec<Gray8>), Error> {
+    let mut pos = 2;
+
+    // Read the next whitespace separated number
+    let field = |pos: &mut usize| -> Result<u32, Error> {
+        skip_whitespace(data, pos);
+
+        let start = *pos;
+        while data.get(*pos).is_some_and(u8::is_ascii_digit) {
+            *pos += 1;
+        }
+
+        std::str::from_utf8(&data[start..*pos])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or(Error::InvalidPnm)
+    };
+
+    let magic = data.get(0..2).ok_or(Error::InvalidPnm)?;
+
+    let width = field(&mut pos)?;
+    let height = field(&mut pos)?;
+    let max = match magic {
+        b"P2" | b"P5" => field(&mut pos)?,
+        _ => 1,
+    };
+
+    if !(1..=255).contains(&max) {
+        return Err(Error::InvalidPnm);
+    }
+
+    let count = width.checked_mul(height).ok_or(Error::InvalidPnm)? as usize;
+
+    // PBM uses 1 for black
+    let scale = |value: u32| Gray8::new((value.min(max) * 255 / max) as u8);
+    let bit = |value: u32| Gray8::new(if value == 0 { 255 } else { 0 });
+
+    let pixels = match magic {
+        b"P1" | b"P2" => {
+            // Every pixel takes at least one byte, so a bogus header can't cause a huge allocation
+            let mut pixels = Vec::with_capacity(count.min(data.len()));
+
+            for _ in 0..count {
+                let value = if magic == b"P1" {
+                    // Plain PBM doesn't need whitespace between pixels
+                    skip_whitespace(data, &mut pos);
+                    pos += 1;
+
+                    match data.get(pos - 1) {
+                        Some(b'0') => bit(0),
+                        Some(b'1') => bit(1),
+                        _ => return Err(Error::InvalidPnm),
+                    }
+                } else {
+                    scale(field(&mut pos)?)
+                };
+
+                pixels.push(value);
+            }
+
+            pixels
+        }
+        b"P4" => {
+            let row_bytes = width.div_ceil(u8::BITS) as usize;
+            let body = data
+                .get(pos + 1..pos + 1 + row_bytes * height as usize)
+                .ok_or(Error::InvalidPnm)?;
+
+            (0..count)
+                .map(|i| {
+                    let (x, y) = (i % width as usize, i / width as usize);
+
+                    bit(u32::from(body[y * row_bytes + x / 8] >> (7 - x % 8) & 1))
+                })
+                .collect()
+        }
+        b"P5" => data
+            .get(pos + 1..pos + 1 + count)
+            .ok_or(Error::InvalidPnm)?
+            .iter()
+            .map(|value| scale(u32::from(*value)))
+            .collect(),
+        _ => return Err(Error::UnsupportedFormat),
+    };
+
+    Ok((Size::new(width, height), pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics_core::pixelcolor::{Gray2, RgbColor};
+    use packed_display_buffer::{buffer_size, PackedBuffer};
+
+    #[test]
+    fn pbm_matches_import() {
+        let pbm = b"P1\n# Icon\n5 10\n01010\n10101\n00000\n11111\n01100\n00110\n10001\n01110\n11011\n00100\n";
+
+        let image = Converter::new(1).convert(pbm).unwrap();
+
+        let imported = PackedBuffer::<5, 10, 10, BinaryColor>::from_pbm(pbm).unwrap();
+
+        assert_eq!(image.data(), imported.as_ref());
+
+        // Comments are allowed between pixels, as with `from_pbm`
+        let commented = b"P1\n2 2\n0 1 # top\n# row\n1 0\n";
+        let image = Converter::new(1).convert(commented).unwrap();
+
+        let imported = PackedBuffer::<2, 2, 2, BinaryColor>::from_pbm(commented).unwrap();
+
+        assert_eq!(image.data(), imported.as_ref());
+
+        let binary = b"P4\n5 2\n\x50\xa8";
+        let image = Converter::new(1).convert(binary).unwrap();
+
+        let imported = PackedBuffer::<5, 2, 5, BinaryColor>::from_pbm(binary).unwrap();
+
+        assert_eq!(image.data(), imported.as_ref());
+    }
+
+    #[test]
+    fn gray_levels() {
+        let pgm = b"P2\n4 3\n15\n0 5 10 15\n15 10 5 0\n3 3 3 3\n";
+
+        let image = Converter::new(2).convert(pgm).unwrap();
+
+        let mut expected = PackedBuffer::<4, 3, { buffer_size::<Gray2>(4, 3) }, Gray2>::new();
+
+        let levels = [[0, 1, 2, 3], [3, 2, 1, 0], [0, 0, 0, 0]];
+
+        for point in expected.bounding_box().points() {
+            expected.set_pixel(
+                point,
+                Gray2::new(levels[point.y as usize][point.x as usize]),
+            );
+        }
+
+        assert_eq!(image.data(), expected.as_ref());
+        assert_eq!(image.color_type(), "Gray2");
+    }
+
+    #[test]
+    fn bmp_matches_runtime_conversion() {
+        let bmp_data = include_bytes!("../../benches/dvd.bmp");
+
+        let image = Converter::new(1).convert(bmp_data).unwrap();
+
+        let bmp = Bmp::<Rgb565>::from_slice(bmp_data).unwrap();
+
+        let mut expected =
+            PackedBuffer::<55, 24, { buffer_size::<BinaryColor>(55, 24) }, BinaryColor>::new();
+
+        for Pixel(point, color) in bmp.pixels() {
+            expected.set_pixel(point, BinaryColor::from(Gray8::from(color).luma() >= 0x80));
+        }
+
+        assert_eq!(image.data(), expected.as_ref());
+    }
+
+    /// Build a single row BMP with the given color table, in `[b, g, r, 0]` entries.
+    fn palette_bmp(bpp: u16, palette: &[[u8; 4]], row: [u8; 4]) -> Vec<u8> {
+        let offset = 14 + 40 + palette.len() as u32 * 4;
+
+        let mut bmp = b"BM".to_vec();
+        bmp.extend((offset + 4).to_le_bytes());
+        bmp.extend(0u32.to_le_bytes());
+        bmp.extend(offset.to_le_bytes());
+
+        // BITMAPINFOHEADER for a 2x1 image
+        for value in [40, 2, 1] {
+            bmp.extend(u32::to_le_bytes(value));
+        }
+        bmp.extend(1u16.to_le_bytes());
+        bmp.extend(bpp.to_le_bytes());
+        for value in [0, 4, 0, 0, palette.len() as u32, 0] {
+            bmp.extend(u32::to_le_bytes(value));
+        }
+
+        bmp.extend(palette.concat());
+        bmp.extend(row);
+
+        bmp
+    }
+
+    #[test]
+    fn bmp_palette_luma() {
+        let red = [0, 0, 255, 0];
+        let green = [0, 255, 0, 0];
+
+        let expected = [Gray8::from(Rgb888::RED), Gray8::from(Rgb888::GREEN)];
+
+        // Pixel indices 0 and 1, at 8bpp and packed MSB first at 1bpp
+        for bmp in [
+            palette_bmp(8, &[red, green], [0, 1, 0, 0]),
+            palette_bmp(1, &[red, green], [0b0100_0000, 0, 0, 0]),
+        ] {
+            let image = Converter::new(8).convert(&bmp).unwrap();
+
+            let mut buffer = PackedBuffer::<2, 1, 2, Gray8>::new();
+            buffer.set_pixel(Point::new(0, 0), expected[0]);
+            buffer.set_pixel(Point::new(1, 0), expected[1]);
+
+            assert_eq!(image.data(), buffer.as_ref());
+
+            // Pure red isn't black, even though its blue channel is zero
+            assert_ne!(image.data()[0], 0);
+        }
+    }
+
+    #[test]
+    fn dithering() {
+        // Flat 50% gray
+        let pgm = [b"P5\n16 16\n255\n".as_slice(), &[0x80; 256]].concat();
+
+        let image = Converter::new(1)
+            .dither(DitherMode::Bayer4)
+            .convert(&pgm)
+            .unwrap();
+
+        let lit: u32 = image.data().iter().map(|byte| byte.count_ones()).sum();
+
+        assert_eq!(lit, 128);
+
+        // Error diffusion is limited by the width of its error buffer, ordered dithering isn't
+        let wide = [b"P5\n1025 1\n255\n".as_slice(), &[0x80; 1025]].concat();
+
+        assert!(matches!(
+            Converter::new(1)
+                .dither(DitherMode::FloydSteinberg)
+                .convert(&wide),
+            Err(Error::TooWide)
+        ));
+        assert!(Converter::new(1)
+            .dither(DitherMode::Bayer8)
+            .convert(&wide)
+            .is_ok());
+    }
+
+    #[test]
+    fn rust_source() {
+        let image = Converter::new(1).convert(b"P1\n2 3\n01\n10\n01\n").unwrap();
+
+        assert_eq!(
+            image.to_rust("ICON"),
+            "/// 2x3 1bpp image in packed vertical page layout.\n\
+             pub const ICON: ::packed_display_buffer::PackedSlice<'static, \
+             ::embedded_graphics_core::pixelcolor::BinaryColor> = \
+             ::packed_display_buffer::PackedSlice::new(\n    &[\n        0x05, 0x02,\n    ],\n    2,\n    3,\n);\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            Converter::new(1).convert(b"GIF89a"),
+            Err(Error::UnsupportedFormat)
+        ));
+        assert!(matches!(
+            Converter::new(1).convert(b"P1\n2 2\n01"),
+            Err(Error::InvalidPnm)
+        ));
+        assert!(matches!(
+            Converter::new(1).convert(b"BM\x00"),
+            Err(Error::Bmp(_))
+        ));
+
+        // 16-bit PGM
+        assert!(matches!(
+            Converter::new(1).convert(b"P5\n1 1\n65535\n\x12\x34"),
+            Err(Error::InvalidPnm)
+        ));
+
+        // Pixel counts that overflow, or are far larger than the data
+        assert!(matches!(
+            Converter::new(1).convert(b"P1\n4294967295 2\n0"),
+            Err(Error::InvalidPnm)
+        ));
+        assert!(matches!(
+            Converter::new(1).convert(b"P2\n65535 65535\n255\n0"),
+            Err(Error::InvalidPnm)
+        ));
+    }
+}
@@ -0,0 +1,461 @@
+use crate::{
+    byte_direction::{ByteDirection, Layout, VerticalByte},
+    mask::slot_bits,
+    PackedBuffer,
+};
+use core::fmt::{self, Write};
+use embedded_graphics_core::{
+    geometry::{Point, Size},
+    pixelcolor::{raw::RawData, IntoStorage, PixelColor},
+    primitives::Rectangle,
+};
+
+/// Characters used to render a buffer as text.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ArtStyle {
+    /// One character per pixel.
+    ///
+    /// 1bpp pixels are drawn as `#` when set and `.` when clear. Deeper pixels are drawn as the
+    /// raw value in hex, using two characters per pixel for depths over 4bpp. This is the format
+    /// read by [`PackedBuffer::from_art`].
+    #[default]
+    Ascii,
+
+    /// Unicode half blocks, with two rows of pixels per line.
+    ///
+    /// Any non-zero pixel is drawn as set.
+    HalfBlock,
+
+    /// Unicode braille patterns, with 2x4 pixels per character.
+    ///
+    /// Any non-zero pixel is drawn as set.
+    Braille,
+}
+
+/// Renders the contents of a [`PackedBuffer`] as text.
+///
+/// This is created with [`PackedBuffer::art`], and implements [`Display`](fmt::Display). Each row
+/// of characters ends with a newline.
+///
+/// Comparing rendered strings in tests gives readable failure messages that show exactly which
+/// pixels differ:
+///
+/// ```rust
+/// use embedded_graphics_core::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle};
+/// use packed_display_buffer::PackedBuffer;
+///
+/// let mut display = PackedBuffer::<6, 4, 6, BinaryColor>::new();
+///
+/// display
+///     .fill_solid(&Rectangle::new(Point::new(1, 1), Size::new(3, 2)), BinaryColor::On)
+///     .unwrap();
+///
+/// assert_eq!(
+///     display.art().to_string(),
+///     "......\n\
+///      .###..\n\
+///      .###..\n\
+///      ......\n"
+/// );
+/// ```
+pub struct Art<'a, const W: u32, const H: u32, const N: usize, C> {
+    buffer: &'a PackedBuffer<W, H, N, C>,
+    area: Rectangle,
+    style: ArtStyle,
+}
+
+impl<'a, const W: u32, const H: u32, const N: usize, C> Art<'a, W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    /// Only render part of the buffer.
+    ///
+    /// The area is clipped to the display dimensions.
+    pub fn area(mut self, area: &Rectangle) -> Self {
+        self.area = area.intersection(&self.buffer.area);
+
+        self
+    }
+
+    /// Set the characters used to render pixels.
+    pub fn style(mut self, style: ArtStyle) -> Self {
+        self.style = style;
+
+        self
+    }
+
+    /// Get the raw value of a pixel, or zero for pixels outside the rendered area.
+    fn raw(&self, x: i32, y: i32) -> u8 {
+        let point = self.area.top_left + Point::new(x, y);
+
+        if self.area.contains(point) {
+            VerticalByte::<W>::get_pixel::<C>(point, &self.buffer.buf)
+        } else {
+            0
+        }
+    }
+
+    fn lit(&self, x: i32, y: i32) -> bool {
+        self.raw(x, y) != 0
+    }
+
+    /// Render `cell` sized blocks of pixels, one row of cells per line.
+    fn cells(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        cell: Size,
+        mut render: impl FnMut(&mut fmt::Formatter<'_>, i32, i32) -> fmt::Result,
+    ) -> fmt::Result {
+        let Size { width, height } = self.area.size;
+
+        for y in (0..height).step_by(cell.height as usize) {
+            for x in (0..width).step_by(cell.width as usize) {
+                render(f, x as i32, y as i32)?;
+            }
+
+            f.write_char('\n')?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> fmt::Debug for Art<'_, W, H, N, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Art")
+            .field("area", &self.area)
+            .field("style", &self.style)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> fmt::Display for Art<'_, W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.style {
+            ArtStyle::Ascii => self.cells(f, Size::new(1, 1), |f, x, y| {
+                write_ascii::<C>(f, self.raw(x, y))
+            }),
+            ArtStyle::HalfBlock => self.cells(f, Size::new(1, 2), |f, x, y| {
+                f.write_char(match (self.lit(x, y), self.lit(x, y + 1)) {
+                    (false, false) => ' ',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (true, true) => '█',
+                })
+            }),
+            ArtStyle::Braille => self.cells(f, Size::new(2, 4), |f, x, y| {
+                let pattern = BRAILLE_DOTS
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (dx, dy))| self.lit(x + dx, y + dy))
+                    .fold(0, |pattern, (bit, _)| pattern | 1 << bit);
+
+                f.write_char(char::from_u32(BRAILLE_BLANK + pattern).unwrap_or(' '))
+            }),
+        }
+    }
+}
+
+/// Write a raw pixel value of color `C` in the [`ArtStyle::Ascii`] format.
+pub(crate) fn write_ascii<C: PixelColor>(f: &mut impl Write, value: u8) -> fmt::Result {
+    match C::Raw::BITS_PER_PIXEL {
+        1 => f.write_char(if value != 0 { '#' } else { '.' }),
+        2..=4 => write!(f, "{:x}", value),
+        _ => write!(f, "{:02x}", value),
+    }
+}
+
+/// The empty braille pattern. Each raised dot sets one bit above this.
+const BRAILLE_BLANK: u32 = 0x2800;
+
+/// Offset of each braille dot within its 2x4 cell, in bit order.
+///
+/// Dots 1-6 run down the left then right column of the original six-dot cell, with the bottom row
+/// added later as dots 7 and 8.
+const BRAILLE_DOTS: [(i32, i32); 8] = [
+    (0, 0),
+    (0, 1),
+    (0, 2),
+    (1, 0),
+    (1, 1),
+    (1, 2),
+    (0, 3),
+    (1, 3),
+];
+
+/// Shows the buffer contents as [`ArtStyle::Ascii`] art, e.g. in the message of a failed assertion.
+impl<const W: u32, const H: u32, const N: usize, C> fmt::Display for PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.art(), f)
+    }
+}
+
+/// Error returned when parsing text art into a buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArtError {
+    /// A character doesn't represent a valid pixel value.
+    InvalidCharacter {
+        /// Row of the invalid character, ignoring blank lines.
+        row: u32,
+
+        /// The invalid character.
+        character: char,
+    },
+
+    /// A row has a different number of pixels to the buffer width.
+    WrongWidth {
+        /// The row with the wrong width, ignoring blank lines.
+        row: u32,
+
+        /// The number of pixels in the row.
+        width: u32,
+    },
+
+    /// The number of rows is different to the buffer height.
+    WrongHeight(u32),
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    /// Render the buffer as text.
+    ///
+    /// See [`Art`] for details.
+    pub fn art(&self) -> Art<'_, W, H, N, C> {
+        Art {
+            buffer: self,
+            area: self.area,
+            style: ArtStyle::Ascii,
+        }
+    }
+
+    /// Create a buffer from text art in the [`ArtStyle::Ascii`] format.
+    ///
+    /// Leading and trailing whitespace on each line is ignored, as are blank lines, so art can be
+    /// indented to line up in string literals. The active area of the returned buffer is empty.
+    ///
+    /// ```rust
+    /// use embedded_graphics_core::{pixelcolor::Gray2, prelude::*};
+    /// use packed_display_buffer::{buffer_size, PackedBuffer};
+    ///
+    /// let display = PackedBuffer::<4, 2, { buffer_size::<Gray2>(4, 2) }, Gray2>::from_art(
+    ///     "
+    ///     0123
+    ///     3210
+    ///     ",
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(display.get_pixel(Point::new(1, 0)), Some(Gray2::new(1)));
+    /// ```
+    pub fn from_art(art: &str) -> Result<Self, ArtError> {
+        let bpp = C::Raw::BITS_PER_PIXEL;
+        let slot = slot_bits(bpp);
+
+        // Depths over 4bpp use two hex digits per pixel
+        let chars = if bpp > 4 { 2 } else { 1 };
+
+        let mut buffer = Self::new();
+        let mut height = 0;
+
+        for line in art.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let row = height;
+            height += 1;
+
+            let len = line.chars().count() as u32;
+
+            if len != W * chars {
+                return Err(ArtError::WrongWidth {
+                    row,
+                    width: len.div_ceil(chars),
+                });
+            }
+
+            // Keep counting rows so the error reports the full height
+            if row >= H {
+                continue;
+            }
+
+            let mut value = 0;
+
+            for (i, character) in line.chars().enumerate() {
+                let digit = match (bpp, character) {
+                    (1, '#') => Some(1),
+                    (1, '.') => Some(0),
+                    (1, _) => None,
+                    (_, c) => c.to_digit(16),
+                }
+                .ok_or(ArtError::InvalidCharacter { row, character })?;
+
+                value = value << 4 | digit;
+
+                if value >= 1 << bpp {
+                    return Err(ArtError::InvalidCharacter { row, character });
+                }
+
+                if (i as u32 + 1).is_multiple_of(chars) {
+                    let point = Point::new(i as i32 / chars as i32, row as i32);
+                    let (idx, shift) = Layout::Vertical.locate(W, point, slot);
+
+                    buffer.buf[idx] |= (value as u8) << shift;
+                    value = 0;
+                }
+            }
+        }
+
+        if height != H {
+            return Err(ArtError::WrongHeight(height));
+        }
+
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_size;
+    use embedded_graphics::pixelcolor::{BinaryColor, Gray2, Gray8};
+
+    const ARROW: &str = "
+        ...#....
+        ..###...
+        .#.#.#..
+        ...#....
+        ...#....
+    ";
+
+    fn arrow() -> PackedBuffer<8, 5, 8, BinaryColor> {
+        PackedBuffer::from_art(ARROW).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let display = arrow();
+
+        assert_eq!(display.get_pixel(Point::new(3, 0)), Some(BinaryColor::On));
+        assert_eq!(display.get_pixel(Point::new(5, 2)), Some(BinaryColor::On));
+        assert_eq!(display.get_pixel(Point::new(4, 0)), Some(BinaryColor::Off));
+        assert_eq!(display.active_area(), Rectangle::zero());
+
+        assert_eq!(
+            display.art().to_string(),
+            "...#....\n\
+             ..###...\n\
+             .#.#.#..\n\
+             ...#....\n\
+             ...#....\n"
+        );
+    }
+
+    #[test]
+    fn gray() {
+        let art = "0123\n3210\n";
+
+        let display =
+            PackedBuffer::<4, 2, { buffer_size::<Gray2>(4, 2) }, Gray2>::from_art(art).unwrap();
+
+        assert_eq!(display.get_pixel(Point::new(3, 0)), Some(Gray2::new(3)));
+        assert_eq!(display.art().to_string(), art);
+
+        let art = "00ff\n7f80\n";
+
+        let display =
+            PackedBuffer::<2, 2, { buffer_size::<Gray8>(2, 2) }, Gray8>::from_art(art).unwrap();
+
+        assert_eq!(display.get_pixel(Point::new(0, 1)), Some(Gray8::new(0x7f)));
+        assert_eq!(display.art().to_string(), art);
+    }
+
+    #[test]
+    fn area() {
+        let display = arrow();
+
+        assert_eq!(
+            display
+                .art()
+                .area(&Rectangle::new(Point::new(1, 1), Size::new(3, 2)))
+                .to_string(),
+            ".##\n#.#\n"
+        );
+
+        // Clipped to the display
+        assert_eq!(
+            display
+                .art()
+                .area(&Rectangle::new(Point::new(6, 3), Size::new(10, 10)))
+                .to_string(),
+            "..\n..\n"
+        );
+    }
+
+    #[test]
+    fn unicode() {
+        let display = arrow();
+
+        assert_eq!(
+            display.art().style(ArtStyle::HalfBlock).to_string(),
+            "  ▄█▄   \n ▀ █ ▀  \n   ▀    \n"
+        );
+
+        assert_eq!(
+            display.art().style(ArtStyle::Braille).to_string(),
+            "⠠⢺⠢⠀\n⠀⠈⠀⠀\n"
+        );
+    }
+
+    #[test]
+    fn display_shows_art() {
+        assert_eq!(
+            arrow().to_string(),
+            "...#....\n..###...\n.#.#.#..\n...#....\n...#....\n"
+        );
+        assert!(format!("{:?}", arrow()).starts_with("PackedBuffer { buf: ["));
+    }
+
+    #[test]
+    fn errors() {
+        type Display = PackedBuffer<3, 2, 3, BinaryColor>;
+
+        assert_eq!(
+            Display::from_art("...\n.x.\n"),
+            Err(ArtError::InvalidCharacter {
+                row: 1,
+                character: 'x'
+            })
+        );
+        assert_eq!(
+            Display::from_art("...\n....\n"),
+            Err(ArtError::WrongWidth { row: 1, width: 4 })
+        );
+        assert_eq!(Display::from_art("...\n"), Err(ArtError::WrongHeight(1)));
+        assert_eq!(
+            Display::from_art("...\n...\n...\n"),
+            Err(ArtError::WrongHeight(3))
+        );
+
+        type Gray = PackedBuffer<2, 1, { buffer_size::<Gray2>(2, 1) }, Gray2>;
+
+        assert_eq!(
+            Gray::from_art("04"),
+            Err(ArtError::InvalidCharacter {
+                row: 0,
+                character: '4'
+            })
+        );
+
+        type Gray8Display = PackedBuffer<2, 1, { buffer_size::<Gray8>(2, 1) }, Gray8>;
+
+        assert_eq!(
+            Gray8Display::from_art("00f"),
+            Err(ArtError::WrongWidth { row: 0, width: 2 })
+        );
+    }
+}
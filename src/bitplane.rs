@@ -0,0 +1,271 @@
+use crate::{block_iterator::BlockIterator, pixels::from_storage, PackedBuffer};
+use core::{convert::Infallible, marker::PhantomData};
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, OriginDimensions, Point, Size},
+    pixelcolor::{raw::RawData, BinaryColor, IntoStorage, PixelColor},
+    primitives::Rectangle,
+    Pixel,
+};
+
+/// A grayscale buffer for monochrome panels, which displays `P` gray levels by alternating frames.
+///
+/// Each bit of the grayscale color `C` is stored in its own 1bpp bitplane, so a `Gray2` buffer has
+/// `P = 2` planes and a `Gray4` buffer `P = 4`. The bitplanes are shown in turn with binary
+/// weighting: plane `k` is shown for `2^k` out of every `2^P - 1` frames. The frame schedule is
+/// interleaved so that heavier planes are spread out over the cycle to reduce flicker.
+///
+/// Each plane is a regular 1bpp [`PackedBuffer`], so frames can be sent to the display with the
+/// same block iterators.
+///
+/// ```rust
+/// use embedded_graphics_core::{pixelcolor::{BinaryColor, Gray2}, prelude::*};
+/// use packed_display_buffer::BitplaneBuffer;
+///
+/// let mut display = BitplaneBuffer::<128, 64, { 128 * 64 / 8 }, Gray2, 2>::new();
+///
+/// display.set_pixel(Point::new(5, 5), Gray2::new(2));
+///
+/// // Plane 1 is shown in 2 of every 3 frames, so the pixel is lit 2/3 of the time
+/// let lit = (0..3)
+///     .filter(|frame| display.frame(*frame).get_pixel(Point::new(5, 5)) == Some(BinaryColor::On))
+///     .count();
+///
+/// assert_eq!(lit, 2);
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct BitplaneBuffer<const W: u32, const H: u32, const N: usize, C, const P: usize> {
+    planes: [PackedBuffer<W, H, N, BinaryColor>; P],
+    _color: PhantomData<C>,
+}
+
+impl<const W: u32, const H: u32, const N: usize, C, const P: usize> BitplaneBuffer<W, H, N, C, P>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+{
+    /// Number of frames in one complete cycle of the schedule.
+    pub const FRAMES: usize = (1 << P) - 1;
+
+    pub const fn new() -> Self {
+        assert!(
+            C::Raw::BITS_PER_PIXEL == P,
+            "Number of bitplanes must equal the color's bit depth"
+        );
+
+        Self {
+            planes: [const { PackedBuffer::new() }; P],
+            _color: PhantomData,
+        }
+    }
+
+    /// Set an individual pixel.
+    ///
+    /// Any given pixels that are outside the display area will be ignored.
+    pub fn set_pixel(&mut self, point: Point, color: C) {
+        let value = color.into_storage();
+
+        for (bit, plane) in self.planes.iter_mut().enumerate() {
+            plane.set_pixel(point, plane_color(value, bit));
+        }
+    }
+
+    /// Get the color of an individual pixel.
+    ///
+    /// Returns `None` if the point is outside the display area.
+    pub fn get_pixel(&self, point: Point) -> Option<C> {
+        let mut value = 0;
+
+        for (bit, plane) in self.planes.iter().enumerate() {
+            if plane.get_pixel(point)?.is_on() {
+                value |= 1 << bit;
+            }
+        }
+
+        Some(from_storage(value))
+    }
+
+    /// Get the bitplane shown during the given frame.
+    ///
+    /// Frame numbers wrap around every [`FRAMES`](Self::FRAMES) frames.
+    pub fn frame_plane(frame: usize) -> usize {
+        let step = frame % Self::FRAMES + 1;
+
+        // Within each cycle, plane `P - 1 - k` is shown on frames whose step has `k` trailing zeros.
+        // This gives plane `k` exactly `2^k` frames per cycle.
+        P - 1 - step.trailing_zeros() as usize
+    }
+
+    /// Get the 1bpp buffer to display during the given frame.
+    pub fn frame(&self, frame: usize) -> &PackedBuffer<W, H, N, BinaryColor> {
+        &self.planes[Self::frame_plane(frame)]
+    }
+
+    /// Get an iterator over every block of the buffer to display during the given frame.
+    ///
+    /// As each frame shows a different bitplane, the whole display needs sending every frame, not
+    /// just the active area.
+    pub fn frame_blocks(&self, frame: usize) -> BlockIterator<'_> {
+        let plane = self.frame(frame);
+
+        plane.blocks(&plane.bounding_box())
+    }
+
+    /// Get a bitplane by its bit index.
+    pub fn plane(&self, bit: usize) -> &PackedBuffer<W, H, N, BinaryColor> {
+        &self.planes[bit]
+    }
+
+    pub fn clear_active_area(&mut self) {
+        self.planes
+            .iter_mut()
+            .for_each(|plane| plane.clear_active_area());
+    }
+
+    /// The area modified since the active area was last cleared.
+    ///
+    /// All planes are written together, so this is the same for every plane.
+    pub fn active_area(&self) -> Rectangle {
+        self.planes
+            .first()
+            .map(|plane| plane.active_area())
+            .unwrap_or_else(Rectangle::zero)
+    }
+}
+
+/// The 1bpp color of the given bit of a grayscale value.
+fn plane_color(value: u8, bit: usize) -> BinaryColor {
+    (value & (1 << bit) != 0).into()
+}
+
+impl<const W: u32, const H: u32, const N: usize, C, const P: usize> Default
+    for BitplaneBuffer<W, H, N, C, P>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C, const P: usize> OriginDimensions
+    for BitplaneBuffer<W, H, N, C, P>
+{
+    fn size(&self) -> Size {
+        Size::new(W, H)
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C, const P: usize> DrawTarget
+    for BitplaneBuffer<W, H, N, C, P>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+{
+    type Color = C;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        pixels
+            .into_iter()
+            .for_each(|Pixel(pos, color)| self.set_pixel(pos, color));
+
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let value = color.into_storage();
+
+        for (bit, plane) in self.planes.iter_mut().enumerate() {
+            plane.fill_solid(area, plane_color(value, bit))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::pixelcolor::{Gray2, Gray4};
+
+    type Gray2Display = BitplaneBuffer<8, 8, 8, Gray2, 2>;
+    type Gray4Display = BitplaneBuffer<8, 8, 8, Gray4, 4>;
+
+    #[test]
+    fn schedule() {
+        let frames: [usize; 3] = core::array::from_fn(Gray2Display::frame_plane);
+
+        assert_eq!(frames, [1, 0, 1]);
+
+        let frames: [usize; 15] = core::array::from_fn(Gray4Display::frame_plane);
+
+        assert_eq!(frames, [3, 2, 3, 1, 3, 2, 3, 0, 3, 2, 3, 1, 3, 2, 3]);
+
+        // Schedule repeats
+        assert_eq!(Gray4Display::frame_plane(15), 3);
+        assert_eq!(Gray4Display::frame_plane(22), 0);
+    }
+
+    #[test]
+    fn brightness_is_weighted() {
+        for level in 0..16 {
+            let mut display = Gray4Display::new();
+
+            display.set_pixel(Point::new(3, 4), Gray4::new(level));
+
+            let lit = (0..Gray4Display::FRAMES)
+                .filter(|frame| {
+                    display.frame(*frame).get_pixel(Point::new(3, 4)).unwrap() == BinaryColor::On
+                })
+                .count();
+
+            assert_eq!(lit, usize::from(level), "{}", level);
+        }
+    }
+
+    #[test]
+    fn readback() {
+        let mut display = Gray4Display::new();
+
+        display.set_pixel(Point::new(1, 1), Gray4::new(0b1010));
+        display.set_pixel(Point::new(1, 1), Gray4::new(0b0110));
+
+        assert_eq!(
+            display.get_pixel(Point::new(1, 1)),
+            Some(Gray4::new(0b0110))
+        );
+        assert_eq!(display.get_pixel(Point::new(8, 1)), None);
+    }
+
+    #[test]
+    fn frame_blocks() {
+        let mut display = Gray2Display::new();
+
+        display
+            .fill_solid(
+                &Rectangle::new(Point::new(2, 0), Size::new(3, 8)),
+                Gray2::new(0b01),
+            )
+            .unwrap();
+
+        assert_eq!(
+            display.active_area(),
+            Rectangle::new(Point::new(2, 0), Size::new(3, 8))
+        );
+
+        // Frame 1 shows plane 0
+        let mut blocks = display.frame_blocks(1);
+        assert_eq!(blocks.next(), Some(&[0, 0, 0xff, 0xff, 0xff, 0, 0, 0][..]));
+        assert_eq!(blocks.next(), None);
+
+        // Frame 0 shows plane 1
+        assert!(display.frame_blocks(0).all(|block| block == [0; 8]));
+
+        display.clear_active_area();
+        assert_eq!(display.active_area(), Rectangle::zero());
+
+        assert_eq!(display.get_pixel(Point::new(3, 3)), Some(Gray2::new(1)));
+    }
+}
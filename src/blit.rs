@@ -0,0 +1,526 @@
+use crate::{
+    buffer_size,
+    mask::{expand, page_mask, slot_bits},
+    pixels::from_storage,
+    PackedBuffer,
+};
+use core::marker::PhantomData;
+use embedded_graphics_core::{
+    geometry::{Dimensions, Point, Size},
+    pixelcolor::{raw::RawData, BinaryColor, IntoStorage, PixelColor},
+    primitives::Rectangle,
+};
+
+/// A borrowed view of packed pixel data in the same vertical page layout as [`PackedBuffer`].
+///
+/// This can be used to [`blit`](PackedBuffer::blit) sprites and icons stored in flash into a
+/// buffer without having to copy them into a `PackedBuffer` first.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PackedSlice<'a, C> {
+    data: &'a [u8],
+    size: Size,
+    _color: PhantomData<C>,
+}
+
+impl<'a, C> PackedSlice<'a, C>
+where
+    C: PixelColor,
+{
+    /// Create a view of `width` x `height` pixels of packed data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `data` doesn't equal [`buffer_size`] for the given dimensions.
+    pub const fn new(data: &'a [u8], width: u32, height: u32) -> Self {
+        assert!(
+            C::Raw::BITS_PER_PIXEL <= 8,
+            "Only pixel formats with <=8bpp can be packed"
+        );
+        assert!(
+            data.len() == buffer_size::<C>(width, height),
+            "Data length doesn't match dimensions"
+        );
+
+        Self {
+            data,
+            size: Size::new(width, height),
+            _color: PhantomData,
+        }
+    }
+
+    /// The packed data.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Read 8 bits of the given column, starting at a bit offset from the top of the column.
+    ///
+    /// The offset may be negative or extend past the bottom of the column, in which case the bits
+    /// outside the column are read as zero.
+    #[inline]
+    pub(crate) fn column_byte(&self, x: u32, bit: i32) -> u8 {
+        column_byte(self.data, self.size.width, x, bit)
+    }
+}
+
+/// Read 8 bits of a column of packed data `width` pixels wide, starting at a bit offset from the
+/// top of the column.
+///
+/// Bits before the start or after the end of the data are read as zero.
+#[inline]
+pub(crate) fn column_byte(data: &[u8], width: u32, x: u32, bit: i32) -> u8 {
+    let page_byte = |page: i32| {
+        if page < 0 {
+            return 0;
+        }
+
+        data.get(page as usize * width as usize + x as usize)
+            .copied()
+            .unwrap_or(0)
+    };
+
+    let page = bit.div_euclid(u8::BITS as i32);
+    let shift = bit.rem_euclid(u8::BITS as i32) as u32;
+
+    let low = page_byte(page) >> shift;
+
+    if shift == 0 {
+        low
+    } else {
+        low | page_byte(page + 1) << (u8::BITS - shift)
+    }
+}
+
+impl<C> PackedSlice<'_, C>
+where
+    C: PixelColor + From<C::Raw>,
+{
+    /// Get the color of an individual pixel.
+    ///
+    /// Returns `None` if the point is outside the slice.
+    pub fn get_pixel(&self, point: Point) -> Option<C> {
+        if !self.bounding_box().contains(point) {
+            return None;
+        }
+
+        let slot = slot_bits(C::Raw::BITS_PER_PIXEL);
+        let value = self.column_byte(point.x as u32, point.y * slot as i32);
+
+        Some(from_storage(value & (u8::MAX >> (u8::BITS - slot))))
+    }
+}
+
+impl<C> Dimensions for PackedSlice<'_, C> {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.size)
+    }
+}
+
+impl<'a, const W: u32, const H: u32, const N: usize, C> From<&'a PackedBuffer<W, H, N, C>>
+    for PackedSlice<'a, C>
+{
+    fn from(buffer: &'a PackedBuffer<W, H, N, C>) -> Self {
+        Self {
+            data: &buffer.buf,
+            size: Size::new(W, H),
+            _color: PhantomData,
+        }
+    }
+}
+
+/// A source area clipped to both the source and destination, and its position in the destination.
+pub(crate) struct ClippedBlit {
+    /// Top left corner of the area in the source.
+    pub src: Point,
+
+    /// Area in the destination.
+    pub dest: Rectangle,
+}
+
+impl ClippedBlit {
+    pub fn new(
+        src_bounds: &Rectangle,
+        src_area: &Rectangle,
+        dest: Point,
+        dest_bounds: &Rectangle,
+    ) -> Self {
+        let src_clipped = src_area.intersection(src_bounds);
+
+        let dest_area = Rectangle::new(
+            dest + (src_clipped.top_left - src_area.top_left),
+            src_clipped.size,
+        )
+        .intersection(dest_bounds);
+
+        Self {
+            src: src_area.top_left + (dest_area.top_left - dest),
+            dest: dest_area,
+        }
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    /// Get a borrowed view of the buffer contents which can be blitted into another buffer.
+    pub fn as_slice(&self) -> PackedSlice<'_, C> {
+        self.into()
+    }
+
+    /// Copy an area of `src` into this buffer, with the top left corner of the area placed at
+    /// `dest`.
+    ///
+    /// The area is clipped to both the source and this buffer. Pixels are combined with the
+    /// existing contents using the current [`raster_op`](Self::raster_op), and the active area is
+    /// updated to cover the copied pixels.
+    ///
+    /// Columns are copied a byte at a time, shifting source bytes across page boundaries when the
+    /// source and destination aren't vertically aligned.
+    pub fn blit(&mut self, src: PackedSlice<'_, C>, src_area: &Rectangle, dest: Point) {
+        let ClippedBlit { src: src_tl, dest } =
+            ClippedBlit::new(&src.bounding_box(), src_area, dest, &self.area);
+
+        let br = if let Some(br) = dest.bottom_right() {
+            br
+        } else {
+            return;
+        };
+
+        self.active_area.update_from_rect(dest);
+
+        let slot = Self::SLOT_BITS;
+        let op = self.op;
+
+        let bit_start = dest.top_left.y as u32 * slot;
+        let bit_end = (br.y as u32 + 1) * slot;
+
+        // Offset to add to a destination bit to get the corresponding source bit
+        let bit_offset = src_tl.y * slot as i32 - bit_start as i32;
+
+        for page in bit_start / u8::BITS..bit_end.div_ceil(u8::BITS) {
+            let page_start = page * u8::BITS;
+            let mask = page_mask(page_start, bit_start, bit_end);
+
+            let row_start = (page * W) as usize + dest.top_left.x as usize;
+            let row = &mut self.buf[row_start..][..dest.size.width as usize];
+
+            for (x, byte) in (src_tl.x as u32..).zip(row.iter_mut()) {
+                let src_byte = src.column_byte(x, page_start as i32 + bit_offset);
+
+                *byte = op.apply(*byte, src_byte, mask);
+            }
+        }
+    }
+
+    /// Copy an area of `src` into this buffer through a 1bpp transparency mask.
+    ///
+    /// Pixels are only copied where the corresponding pixel in `mask` is `On`, i.e. each byte is
+    /// written as `(dst & !mask) | (src & mask)` when using [`RasterOp::Replace`]. Other raster
+    /// operations are applied only to the masked pixels. Clipping and active area handling is the
+    /// same as [`blit`](Self::blit).
+    ///
+    /// For colors wider than 1bpp, each mask bit is expanded to cover the whole pixel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mask` is not the same size as `src`.
+    ///
+    /// [`RasterOp::Replace`]: crate::RasterOp::Replace
+    pub fn blit_masked(
+        &mut self,
+        src: PackedSlice<'_, C>,
+        mask: PackedSlice<'_, BinaryColor>,
+        src_area: &Rectangle,
+        dest: Point,
+    ) {
+        assert_eq!(src.size(), mask.size(), "Mask must be the same size as src");
+
+        let ClippedBlit { src: src_tl, dest } =
+            ClippedBlit::new(&src.bounding_box(), src_area, dest, &self.area);
+
+        let br = if let Some(br) = dest.bottom_right() {
+            br
+        } else {
+            return;
+        };
+
+        self.active_area.update_from_rect(dest);
+
+        let slot = Self::SLOT_BITS;
+        let op = self.op;
+
+        let bit_start = dest.top_left.y as u32 * slot;
+        let bit_end = (br.y as u32 + 1) * slot;
+
+        let bit_offset = src_tl.y * slot as i32 - bit_start as i32;
+
+        // Offset to add to a destination row to get the corresponding mask row
+        let row_offset = src_tl.y - dest.top_left.y;
+
+        for page in bit_start / u8::BITS..bit_end.div_ceil(u8::BITS) {
+            let page_start = page * u8::BITS;
+            let page_mask = page_mask(page_start, bit_start, bit_end);
+
+            let mask_row = (page_start / slot) as i32 + row_offset;
+
+            let row_start = (page * W) as usize + dest.top_left.x as usize;
+            let row = &mut self.buf[row_start..][..dest.size.width as usize];
+
+            for (x, byte) in (src_tl.x as u32..).zip(row.iter_mut()) {
+                let src_byte = src.column_byte(x, page_start as i32 + bit_offset);
+                let mask_byte = expand(mask.column_byte(x, mask_row), slot);
+
+                *byte = op.apply(*byte, src_byte, page_mask & mask_byte);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RasterOp;
+    use embedded_graphics::pixelcolor::Gray2;
+    use embedded_graphics_core::{primitives::PointsIter, Pixel};
+
+    type Display = PackedBuffer<24, 24, { 24 * 24 / 8 }, BinaryColor>;
+
+    /// A sprite with a distinct pattern in every column.
+    fn sprite() -> PackedBuffer<10, 13, { buffer_size::<BinaryColor>(10, 13) }, BinaryColor> {
+        let mut sprite = PackedBuffer::new();
+
+        for point in sprite.bounding_box().points() {
+            let on = (point.x * 7 + point.y * 3) % 5 < 2;
+            sprite.set_pixel(point, on.into());
+        }
+
+        sprite
+    }
+
+    fn blit_pixels<const W: u32, const H: u32, const N: usize, C>(
+        dest: &mut PackedBuffer<W, H, N, C>,
+        src: PackedSlice<'_, C>,
+        src_area: &Rectangle,
+        offset: Point,
+    ) where
+        C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+    {
+        for point in src_area.points() {
+            if let Some(color) = src.get_pixel(point) {
+                dest.set_pixel(point - src_area.top_left + offset, color);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_set_pixel() {
+        let sprite = sprite();
+
+        let src_areas = [
+            sprite.bounding_box(),
+            Rectangle::new(Point::new(2, 3), Size::new(5, 9)),
+            Rectangle::new(Point::new(-2, -5), Size::new(8, 12)),
+        ];
+
+        for src_area in src_areas {
+            for dest in [
+                Point::new(0, 0),
+                Point::new(3, 5),
+                Point::new(5, 8),
+                Point::new(-4, -3),
+                Point::new(20, 18),
+            ] {
+                let mut blit = Display::new();
+                let mut pixels = Display::new();
+
+                blit.fill_rect(
+                    &Rectangle::new(Point::new(4, 4), Size::new(12, 12)),
+                    BinaryColor::On,
+                );
+                pixels.fill_rect(
+                    &Rectangle::new(Point::new(4, 4), Size::new(12, 12)),
+                    BinaryColor::On,
+                );
+
+                blit.blit(sprite.as_slice(), &src_area, dest);
+                blit_pixels(&mut pixels, sprite.as_slice(), &src_area, dest);
+
+                assert_eq!(
+                    blit.as_ref(),
+                    pixels.as_ref(),
+                    "{:?} -> {:?}",
+                    src_area,
+                    dest
+                );
+                assert_eq!(
+                    blit.active_area(),
+                    pixels.active_area(),
+                    "{:?} -> {:?}",
+                    src_area,
+                    dest
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn raster_op() {
+        let sprite = sprite();
+
+        let mut blit = Display::new();
+        let mut pixels = Display::new();
+
+        blit.fill_rect(
+            &Rectangle::new(Point::new(0, 6), Size::new(24, 6)),
+            BinaryColor::On,
+        );
+        pixels.fill_rect(
+            &Rectangle::new(Point::new(0, 6), Size::new(24, 6)),
+            BinaryColor::On,
+        );
+
+        blit.set_raster_op(RasterOp::Xor);
+        pixels.set_raster_op(RasterOp::Xor);
+
+        blit.blit(sprite.as_slice(), &sprite.bounding_box(), Point::new(7, 3));
+        blit_pixels(
+            &mut pixels,
+            sprite.as_slice(),
+            &sprite.bounding_box(),
+            Point::new(7, 3),
+        );
+
+        let diff = blit.diff(&pixels);
+        assert!(diff.is_empty(), "{}", diff);
+    }
+
+    #[test]
+    fn gray2() {
+        let mut sprite = PackedBuffer::<5, 7, { buffer_size::<Gray2>(5, 7) }, Gray2>::new();
+
+        for point in sprite.bounding_box().points() {
+            sprite.set_pixel(point, Gray2::new(((point.x + point.y) % 4) as u8));
+        }
+
+        let mut blit = PackedBuffer::<16, 16, { buffer_size::<Gray2>(16, 16) }, Gray2>::new();
+        let mut pixels = PackedBuffer::<16, 16, { buffer_size::<Gray2>(16, 16) }, Gray2>::new();
+
+        blit.blit(sprite.as_slice(), &sprite.bounding_box(), Point::new(3, 3));
+        blit_pixels(
+            &mut pixels,
+            sprite.as_slice(),
+            &sprite.bounding_box(),
+            Point::new(3, 3),
+        );
+
+        let diff = blit.diff(&pixels);
+        assert!(diff.is_empty(), "{}", diff);
+    }
+
+    #[test]
+    fn borrowed_slice() {
+        // 3x8 arrow, one byte per column
+        const ARROW: PackedSlice<'static, BinaryColor> =
+            PackedSlice::new(&[0b0001_1000, 0b0011_1100, 0b0111_1110], 3, 8);
+
+        let mut display = Display::new();
+
+        display.blit(ARROW, &ARROW.bounding_box(), Point::new(1, 4));
+
+        let mut blocks = display.active_blocks();
+
+        assert_eq!(
+            blocks.next(),
+            Some(&[0b1000_0000, 0b1100_0000, 0b1110_0000][..])
+        );
+        assert_eq!(
+            blocks.next(),
+            Some(&[0b0000_0001, 0b0000_0011, 0b0000_0111][..])
+        );
+        assert_eq!(blocks.next(), None);
+    }
+
+    #[test]
+    fn masked() {
+        let sprite = sprite();
+
+        // Circular-ish mask
+        let mut mask =
+            PackedBuffer::<10, 13, { buffer_size::<BinaryColor>(10, 13) }, BinaryColor>::new();
+        for point in mask.bounding_box().points() {
+            let d = (point - Point::new(5, 6)).abs();
+            mask.set_pixel(point, (d.x * d.x + d.y * d.y < 25).into());
+        }
+
+        for dest in [Point::new(0, 0), Point::new(5, 3), Point::new(-3, 17)] {
+            let mut blit = Display::new();
+            let mut pixels = Display::new();
+
+            // Striped background
+            for y in (0..24).step_by(3) {
+                let stripe = Rectangle::new(Point::new(0, y), Size::new(24, 1));
+                blit.fill_rect(&stripe, BinaryColor::On);
+                pixels.fill_rect(&stripe, BinaryColor::On);
+            }
+
+            blit.blit_masked(
+                sprite.as_slice(),
+                mask.as_slice(),
+                &sprite.bounding_box(),
+                dest,
+            );
+
+            for point in sprite.bounding_box().points() {
+                if mask.get_pixel(point) == Some(BinaryColor::On) {
+                    pixels.set_pixel(point + dest, sprite.get_pixel(point).unwrap());
+                }
+            }
+
+            assert_eq!(blit.as_ref(), pixels.as_ref(), "{:?}", dest);
+        }
+    }
+
+    #[test]
+    fn masked_gray2() {
+        let mut sprite = PackedBuffer::<6, 6, { buffer_size::<Gray2>(6, 6) }, Gray2>::new();
+        for point in sprite.bounding_box().points() {
+            sprite.set_pixel(point, Gray2::new(0b10));
+        }
+
+        // Checkerboard mask
+        let mut mask =
+            PackedBuffer::<6, 6, { buffer_size::<BinaryColor>(6, 6) }, BinaryColor>::new();
+        for point in mask.bounding_box().points() {
+            mask.set_pixel(point, ((point.x + point.y) % 2 == 0).into());
+        }
+
+        let mut display = PackedBuffer::<8, 8, { buffer_size::<Gray2>(8, 8) }, Gray2>::new();
+        for point in display.bounding_box().points() {
+            display.set_pixel(point, Gray2::new(0b01));
+        }
+
+        display.blit_masked(
+            sprite.as_slice(),
+            mask.as_slice(),
+            &sprite.bounding_box(),
+            Point::new(1, 1),
+        );
+
+        for Pixel(point, color) in display.pixels() {
+            let local = point - Point::new(1, 1);
+
+            let expected = if mask.get_pixel(local) == Some(BinaryColor::On) {
+                0b10
+            } else {
+                0b01
+            };
+
+            assert_eq!(color, Gray2::new(expected), "{:?}", point);
+        }
+    }
+}
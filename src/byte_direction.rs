@@ -1,78 +1,132 @@
+use crate::{
+    mask::{slot_bits, slot_mask},
+    raster_op::RasterOp,
+};
 use embedded_graphics_core::{
     geometry::Point,
     pixelcolor::{raw::RawData, IntoStorage, PixelColor},
 };
 
 pub trait ByteDirection {
+    /// Find the byte index and bit shift of the slot holding the pixel at the given point.
+    fn locate(p: Point, slot: u32) -> (usize, u32);
+
+    #[inline]
+    #[allow(dead_code)]
     fn set_pixel<C>(p: Point, color: C, buf: &mut [u8])
     where
-        C: PixelColor + IntoStorage<Storage = u8>;
-}
-
-pub struct VerticalByte<const W: usize>;
+        C: PixelColor + IntoStorage<Storage = u8>,
+    {
+        Self::merge_pixel(p, color, RasterOp::Replace, buf)
+    }
 
-impl<const W: usize> ByteDirection for VerticalByte<W> {
+    /// Combine a pixel with the existing value in the buffer using the given raster operation.
     #[inline]
-    fn set_pixel<C>(p: Point, color: C, buf: &mut [u8])
+    fn merge_pixel<C>(p: Point, color: C, op: RasterOp, buf: &mut [u8])
     where
         C: PixelColor + IntoStorage<Storage = u8>,
     {
-        let color: u8 = color.into_storage();
+        let slot = slot_bits(C::Raw::BITS_PER_PIXEL);
+        let (byte_index, shift) = Self::locate(p, slot);
 
-        let x = p.x.unsigned_abs();
-        let y = p.y.unsigned_abs();
+        let mask = slot_mask(slot) << shift;
+        let color = color.into_storage() << shift;
 
-        let bpp = C::Raw::BITS_PER_PIXEL as u32;
+        buf[byte_index] = op.apply(buf[byte_index], color, mask);
+    }
 
-        let bit_y = y * bpp;
-        let byte_y = bit_y as usize / 8;
-        let index_in_byte = bit_y % 8 / bpp;
+    /// Read the raw storage value of the pixel at the given point.
+    #[inline]
+    fn get_pixel<C>(p: Point, buf: &[u8]) -> u8
+    where
+        C: PixelColor,
+    {
+        let slot = slot_bits(C::Raw::BITS_PER_PIXEL);
+        let (byte_index, shift) = Self::locate(p, slot);
 
-        let pixel_mask = 2u8.pow(bpp) - 1;
-        let shift = index_in_byte * bpp;
-        let mask = pixel_mask << shift;
-        let color = color << shift;
+        (buf[byte_index] >> shift) & slot_mask(slot)
+    }
+}
 
-        let byte_index = (byte_y * W) + x as usize;
+/// Pixels are stored in vertical columns, with the top pixel of each page in the LSB.
+pub struct VerticalByte<const W: u32>;
 
-        buf[byte_index] = buf[byte_index] & !mask | color;
+impl<const W: u32> ByteDirection for VerticalByte<W> {
+    #[inline]
+    fn locate(p: Point, slot: u32) -> (usize, u32) {
+        Layout::Vertical.locate(W, p, slot)
     }
 }
 
-pub struct HorizontalByte<const W: usize>;
+/// Pixels are stored in horizontal rows, with the leftmost pixel of each byte in the MSB.
+#[allow(dead_code)]
+pub struct HorizontalByte<const W: u32>;
 
-impl<const W: usize> ByteDirection for HorizontalByte<W> {
-    fn set_pixel<C>(p: Point, color: C, buf: &mut [u8])
-    where
-        C: PixelColor + IntoStorage<Storage = u8>,
-    {
-        let color: u8 = color.into_storage();
+impl<const W: u32> ByteDirection for HorizontalByte<W> {
+    #[inline]
+    fn locate(p: Point, slot: u32) -> (usize, u32) {
+        Layout::Horizontal.locate(W, p, slot)
+    }
+}
 
+/// How pixels are arranged in the bytes of a buffer.
+///
+/// This is used when reading or writing raw buffer data, for example to export the contents of a
+/// display whose controller expects a different layout to [`PackedBuffer`](crate::PackedBuffer).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Layout {
+    /// Pixels are stored in vertical pages, with the top pixel of each page in the LSB.
+    ///
+    /// This is the layout used by [`PackedBuffer`](crate::PackedBuffer).
+    Vertical,
+
+    /// Pixels are stored in horizontal rows, with the leftmost pixel of each byte in the MSB.
+    ///
+    /// Each row is padded to a whole number of bytes.
+    Horizontal,
+}
+
+impl Layout {
+    /// Find the byte index and bit shift of the slot holding the pixel at the given point.
+    #[inline]
+    pub(crate) fn locate(self, width: u32, p: Point, slot: u32) -> (usize, u32) {
         let x = p.x.unsigned_abs();
         let y = p.y.unsigned_abs();
 
-        let bpp = C::Raw::BITS_PER_PIXEL as u32;
-        let bytes_per_row = W as u32 * bpp / 8;
+        match self {
+            Layout::Vertical => {
+                let bit_y = y * slot;
+                let byte_y = bit_y / u8::BITS;
+                let shift = bit_y % u8::BITS;
 
-        let bit_x = x * bpp;
-        let byte_x = bit_x / 8;
-        let index_in_byte = (7 - bit_x) % 8 / bpp;
+                ((byte_y * width + x) as usize, shift)
+            }
+            Layout::Horizontal => {
+                let bytes_per_row = (width * slot).div_ceil(u8::BITS);
 
-        let pixel_mask = 2u8.pow(bpp) - 1;
-        let shift = index_in_byte * bpp;
-        let mask = pixel_mask << shift;
-        let color = color << shift;
+                let bit_x = x * slot;
+                let byte_x = bit_x / u8::BITS;
+                let shift = u8::BITS - slot - bit_x % u8::BITS;
 
-        let byte_index = (byte_x + y * bytes_per_row) as usize;
+                ((byte_x + y * bytes_per_row) as usize, shift)
+            }
+        }
+    }
 
-        buf[byte_index] = buf[byte_index] & !mask | color;
+    /// Number of bytes needed to store an image of the given size with `slot` bits per pixel.
+    pub(crate) fn len(self, width: u32, height: u32, slot: u32) -> usize {
+        match self {
+            Layout::Vertical => (width * (height * slot).div_ceil(u8::BITS)) as usize,
+            Layout::Horizontal => ((width * slot).div_ceil(u8::BITS) * height) as usize,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use embedded_graphics::pixelcolor::Gray2;
+    use embedded_graphics::pixelcolor::{Gray2, Gray4, Gray8};
 
     #[test]
     fn set_vertical() {
@@ -94,4 +148,63 @@ mod tests {
             println!("{:08b}", i);
         }
     }
+
+    #[test]
+    fn vertical_8bpp() {
+        let mut buf = [0u8; 8];
+
+        VerticalByte::<4>::set_pixel::<Gray8>(Point::new(1, 1), Gray8::new(0xaa), &mut buf);
+
+        assert_eq!(buf, [0, 0, 0, 0, 0, 0xaa, 0, 0]);
+    }
+
+    #[test]
+    fn vertical_page_boundary() {
+        let mut buf = [0u8; 8];
+
+        // Second 4 bit slot of the second page
+        VerticalByte::<4>::set_pixel::<Gray4>(Point::new(2, 3), Gray4::new(0b1001), &mut buf);
+
+        assert_eq!(buf, [0, 0, 0, 0, 0, 0, 0b1001_0000, 0]);
+    }
+
+    #[test]
+    fn horizontal_second_byte() {
+        let mut buf = [0u8; 4];
+
+        // 6 pixels wide at 2bpp is 12 bits, so each row occupies 2 bytes
+        HorizontalByte::<6>::set_pixel::<Gray2>(Point::new(4, 1), Gray2::new(0b10), &mut buf);
+
+        assert_eq!(buf, [0, 0, 0, 0b1000_0000]);
+    }
+
+    #[test]
+    fn overwrite_clears_slot() {
+        let mut buf = [0xffu8; 2];
+
+        VerticalByte::<2>::set_pixel::<Gray4>(Point::new(1, 0), Gray4::new(0b0101), &mut buf);
+
+        assert_eq!(buf, [0xff, 0b1111_0101]);
+    }
+
+    #[test]
+    fn get_round_trip() {
+        let mut buf = [0u8; 8];
+
+        VerticalByte::<4>::set_pixel::<Gray2>(Point::new(2, 5), Gray2::new(2), &mut buf);
+        HorizontalByte::<4>::set_pixel::<Gray2>(Point::new(1, 7), Gray2::new(1), &mut buf);
+
+        assert_eq!(
+            VerticalByte::<4>::get_pixel::<Gray2>(Point::new(2, 5), &buf),
+            2
+        );
+        assert_eq!(
+            HorizontalByte::<4>::get_pixel::<Gray2>(Point::new(1, 7), &buf),
+            1
+        );
+        assert_eq!(
+            VerticalByte::<4>::get_pixel::<Gray2>(Point::new(2, 4), &buf),
+            0
+        );
+    }
 }
@@ -0,0 +1,382 @@
+use crate::{active_area::ActiveArea, block_iterator::BlockIterator, buffer_size, skip};
+use core::{convert::Infallible, marker::PhantomData};
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::{raw::RawData, IntoStorage, PixelColor},
+    primitives::Rectangle,
+    Pixel,
+};
+
+/// Byte order used to store multi-byte pixels.
+pub trait Endianness {
+    /// Write the lowest `bytes.len()` bytes of `value` into `bytes`.
+    fn write(value: u32, bytes: &mut [u8]);
+}
+
+/// Most significant byte first.
+///
+/// This is the order most SPI TFT controllers expect pixel data to be sent in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BigEndian;
+
+impl Endianness for BigEndian {
+    #[inline]
+    fn write(value: u32, bytes: &mut [u8]) {
+        let len = bytes.len();
+
+        bytes.copy_from_slice(&value.to_be_bytes()[4 - len..]);
+    }
+}
+
+/// Least significant byte first.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LittleEndian;
+
+impl Endianness for LittleEndian {
+    #[inline]
+    fn write(value: u32, bytes: &mut [u8]) {
+        let len = bytes.len();
+
+        bytes.copy_from_slice(&value.to_le_bytes()[..len]);
+    }
+}
+
+/// A row-major display buffer for colors wider than 8 bits per pixel, e.g. `Rgb565` or `Rgb888`.
+///
+/// Each pixel occupies a whole number of bytes, so `Rgb565` takes 2 bytes and `Rgb666` and `Rgb888`
+/// take 3. Raw values are stored right aligned in the given byte order `E`.
+///
+/// Like [`PackedBuffer`](crate::PackedBuffer), the buffer tracks the area modified since the last
+/// call to [`clear_active_area`](ColorBuffer::clear_active_area) so partial updates can be sent to
+/// the display.
+// TODO: Remove `N` and calculate from W * H when const features allow us to do so.
+#[derive(Debug, PartialEq)]
+pub struct ColorBuffer<const W: u32, const H: u32, const N: usize, C, E = BigEndian> {
+    buf: [u8; N],
+    area: Rectangle,
+    active_area: ActiveArea<W, H>,
+    _color: PhantomData<(C, E)>,
+}
+
+impl<const W: u32, const H: u32, const N: usize, C, E> ColorBuffer<W, H, N, C, E>
+where
+    C: PixelColor + IntoStorage,
+    <C as IntoStorage>::Storage: Into<u32>,
+    E: Endianness,
+{
+    /// Number of bytes each pixel occupies in the buffer.
+    const BYTES_PER_PIXEL: usize = C::Raw::BITS_PER_PIXEL.div_ceil(u8::BITS as usize);
+
+    pub const fn new() -> Self {
+        assert!(
+            C::Raw::BITS_PER_PIXEL > 8,
+            "Use PackedBuffer for pixel formats with <=8bpp"
+        );
+
+        // TODO: Remove this when we can do maths in const generics
+        if N != buffer_size::<C>(W, H) {
+            panic!("Invariant error: N != buffer_size(W, H)")
+        }
+
+        Self {
+            buf: [0x00u8; N],
+            area: Rectangle::new(Point::zero(), Size::new(W, H)),
+            active_area: ActiveArea::new(),
+            _color: PhantomData,
+        }
+    }
+
+    /// Set an individual pixel.
+    ///
+    /// Any given pixels that are outside the display area will be ignored.
+    pub fn set_pixel(&mut self, point: Point, color: C) {
+        if !self.area.contains(point) {
+            return;
+        }
+
+        let idx = self.pixel_index(point);
+
+        E::write(
+            color.into_storage().into(),
+            &mut self.buf[idx..][..Self::BYTES_PER_PIXEL],
+        );
+
+        self.active_area.update_from_point(point);
+    }
+
+    /// Byte index of the given point, which must lie inside the display area.
+    fn pixel_index(&self, point: Point) -> usize {
+        (point.y as usize * W as usize + point.x as usize) * Self::BYTES_PER_PIXEL
+    }
+
+    /// The bytes for `width` pixels starting at `point`.
+    fn row_range(&mut self, point: Point, width: u32) -> &mut [u8] {
+        let start_idx = self.pixel_index(point);
+
+        &mut self.buf[start_idx..][..width as usize * Self::BYTES_PER_PIXEL]
+    }
+
+    /// Fill the given area with a single color.
+    ///
+    /// The area is clipped to the display dimensions. The color is encoded once and then copied
+    /// into each row of the area.
+    fn fill_rect(&mut self, rect: &Rectangle, color: C) {
+        let rect = rect.intersection(&self.area);
+
+        if rect.is_zero_sized() {
+            return;
+        }
+
+        self.active_area.update_from_rect(rect);
+
+        let mut pixel = [0u8; 4];
+        let pixel = &mut pixel[..Self::BYTES_PER_PIXEL];
+        E::write(color.into_storage().into(), pixel);
+
+        for y in rect.rows() {
+            let point = Point::new(rect.top_left.x, y);
+
+            self.row_range(point, rect.size.width)
+                .chunks_exact_mut(Self::BYTES_PER_PIXEL)
+                .for_each(|bytes| bytes.copy_from_slice(pixel));
+        }
+    }
+
+    /// Contiguous fill.
+    ///
+    /// Pixels outside the display area are skipped in whole runs rather than being bounds checked
+    /// one by one.
+    fn fill_rect_iter<I>(&mut self, rect: &Rectangle, colors: I)
+    where
+        I: IntoIterator<Item = C>,
+    {
+        let intersection = rect.intersection(&self.area);
+
+        // Don't draw anything if the entire rect lies outside the visible area
+        if intersection.is_zero_sized() {
+            return;
+        }
+
+        self.active_area.update_from_rect(intersection);
+
+        let mut colors = colors.into_iter();
+
+        let width = rect.size.width as usize;
+        let visible_width = intersection.size.width;
+
+        // Pixels to skip at the start and end of each row
+        let skip_left = (intersection.top_left.x - rect.top_left.x) as usize;
+        let skip_right = width - skip_left - visible_width as usize;
+
+        // Rows above the visible area
+        let skip_top = (intersection.top_left.y - rect.top_left.y) as usize;
+        skip(&mut colors, skip_top * width);
+
+        for y in intersection.rows() {
+            skip(&mut colors, skip_left);
+
+            let row = self.row_range(Point::new(intersection.top_left.x, y), visible_width);
+
+            for (bytes, color) in row
+                .chunks_exact_mut(Self::BYTES_PER_PIXEL)
+                .zip(colors.by_ref())
+            {
+                E::write(color.into_storage().into(), bytes);
+            }
+
+            skip(&mut colors, skip_right);
+        }
+    }
+
+    pub fn clear_active_area(&mut self) {
+        self.active_area.clear();
+    }
+
+    pub fn active_area(&self) -> Rectangle {
+        self.active_area.rectangle()
+    }
+
+    /// Get an iterator over the rows of the active area of the display buffer.
+    ///
+    /// Each item is the slice of bytes covering the active area in a single row, which can be sent
+    /// to the display after setting its column and page address window to the active area.
+    pub fn active_blocks(&self) -> BlockIterator<'_> {
+        let active_area = self.active_area.rectangle();
+
+        if active_area.is_zero_sized() {
+            return BlockIterator::empty();
+        }
+
+        let start_idx = (active_area.top_left.y as usize * W as usize
+            + active_area.top_left.x as usize)
+            * Self::BYTES_PER_PIXEL;
+
+        BlockIterator {
+            buffer: &self.buf,
+            display_width: W as usize * Self::BYTES_PER_PIXEL,
+            buffer_idx: start_idx,
+            block_width: active_area.size.width as usize * Self::BYTES_PER_PIXEL,
+            num_blocks: active_area.size.height,
+            current_block: 0,
+        }
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C, E> Default for ColorBuffer<W, H, N, C, E>
+where
+    C: PixelColor + IntoStorage,
+    <C as IntoStorage>::Storage: Into<u32>,
+    E: Endianness,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C, E> AsRef<[u8]> for ColorBuffer<W, H, N, C, E> {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C, E> OriginDimensions
+    for ColorBuffer<W, H, N, C, E>
+{
+    fn size(&self) -> Size {
+        self.area.size
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C, E> DrawTarget for ColorBuffer<W, H, N, C, E>
+where
+    C: PixelColor + IntoStorage,
+    <C as IntoStorage>::Storage: Into<u32>,
+    E: Endianness,
+{
+    type Color = C;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        // NOTE: Don't need to filter here as `set_pixel` already does bounds checking
+        pixels
+            .into_iter()
+            .for_each(|Pixel(pos, color)| self.set_pixel(pos, color));
+
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.fill_rect(area, color);
+
+        Ok(())
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.fill_rect_iter(area, colors);
+
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        let area = self.area;
+
+        self.fill_rect(&area, color);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::pixelcolor::{Rgb565, Rgb888, RgbColor};
+    use embedded_graphics_core::primitives::PointsIter;
+
+    type Tft565 = ColorBuffer<8, 4, { buffer_size::<Rgb565>(8, 4) }, Rgb565>;
+
+    #[test]
+    fn endianness() {
+        let mut be = Tft565::new();
+        let mut le = ColorBuffer::<8, 4, { 8 * 4 * 2 }, Rgb565, LittleEndian>::new();
+
+        let color = Rgb565::new(0b11111, 0, 0b00001);
+
+        be.set_pixel(Point::new(1, 0), color);
+        le.set_pixel(Point::new(1, 0), color);
+
+        assert_eq!(be.as_ref()[2..4], [0b1111_1000, 0b0000_0001]);
+        assert_eq!(le.as_ref()[2..4], [0b0000_0001, 0b1111_1000]);
+    }
+
+    #[test]
+    fn rgb888_three_bytes() {
+        let mut buffer = ColorBuffer::<2, 2, { buffer_size::<Rgb888>(2, 2) }, Rgb888>::new();
+
+        buffer.set_pixel(Point::new(0, 1), Rgb888::new(0x11, 0x22, 0x33));
+
+        assert_eq!(buffer.as_ref().len(), 12);
+        assert_eq!(buffer.as_ref()[6..9], [0x11, 0x22, 0x33]);
+    }
+
+    #[test]
+    fn fill_matches_set_pixel() {
+        let area = Rectangle::new(Point::new(-2, 1), Size::new(5, 10));
+
+        let mut fill = Tft565::new();
+        let mut pixels = Tft565::new();
+
+        fill.fill_solid(&area, Rgb565::CYAN).unwrap();
+
+        for point in area.points() {
+            pixels.set_pixel(point, Rgb565::CYAN);
+        }
+
+        assert_eq!(fill, pixels);
+        assert_eq!(
+            fill.active_area(),
+            Rectangle::new(Point::new(0, 1), Size::new(3, 3))
+        );
+    }
+
+    #[test]
+    fn contiguous_matches_set_pixel() {
+        let area = Rectangle::new(Point::new(5, -1), Size::new(4, 3));
+        let colors = (0..12u16).map(|i| Rgb565::new(i as u8, 0, 0));
+
+        let mut fill = Tft565::new();
+        let mut pixels = Tft565::new();
+
+        fill.fill_contiguous(&area, colors.clone()).unwrap();
+
+        for (point, color) in area.points().zip(colors) {
+            pixels.set_pixel(point, color);
+        }
+
+        assert_eq!(fill, pixels);
+    }
+
+    #[test]
+    fn active_rows() {
+        let mut buffer = Tft565::new();
+
+        buffer
+            .fill_solid(
+                &Rectangle::new(Point::new(2, 1), Size::new(3, 2)),
+                Rgb565::WHITE,
+            )
+            .unwrap();
+
+        let mut rows = buffer.active_blocks();
+
+        assert_eq!(rows.next(), Some(&[0xff; 6][..]));
+        assert_eq!(rows.next(), Some(&[0xff; 6][..]));
+        assert_eq!(rows.next(), None);
+    }
+}
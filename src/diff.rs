@@ -0,0 +1,307 @@
+use crate::{
+    art::write_ascii,
+    byte_direction::{ByteDirection, VerticalByte},
+    pixels::from_storage,
+    PackedBuffer,
+};
+use core::fmt::{self, Write};
+use embedded_graphics_core::{
+    geometry::Point,
+    pixelcolor::{IntoStorage, PixelColor},
+    primitives::{rectangle, PointsIter, Rectangle},
+};
+
+/// Pixel level differences between two [`PackedBuffer`]s.
+///
+/// Created by [`PackedBuffer::diff`]. The [`Display`](fmt::Display) implementation prints the
+/// area containing the differences from both buffers side by side, with differing pixels marked,
+/// which makes it useful as an assertion message:
+///
+/// ```rust
+/// use embedded_graphics_core::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle};
+/// use packed_display_buffer::PackedBuffer;
+///
+/// let mut expected = PackedBuffer::<16, 8, 16, BinaryColor>::new();
+/// let mut actual = PackedBuffer::<16, 8, 16, BinaryColor>::new();
+///
+/// let area = Rectangle::new(Point::new(2, 3), Size::new(5, 4));
+/// expected.fill_solid(&area, BinaryColor::On).unwrap();
+/// actual.fill_contiguous(&area, core::iter::repeat(BinaryColor::On)).unwrap();
+///
+/// let diff = actual.diff(&expected);
+/// assert!(diff.is_empty(), "{}", diff);
+/// ```
+pub struct Diff<'a, const W: u32, const H: u32, const N: usize, C> {
+    left: &'a PackedBuffer<W, H, N, C>,
+    right: &'a PackedBuffer<W, H, N, C>,
+}
+
+impl<'a, const W: u32, const H: u32, const N: usize, C> Diff<'a, W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+{
+    /// Returns `true` if both buffers have the same pixels.
+    pub fn is_empty(&self) -> bool {
+        self.pixels().next().is_none()
+    }
+
+    /// The number of pixels that differ.
+    pub fn count(&self) -> usize {
+        self.pixels().count()
+    }
+
+    /// The smallest rectangle containing every differing pixel.
+    ///
+    /// This is a zero sized rectangle if the buffers are the same.
+    pub fn bounding_box(&self) -> Rectangle {
+        self.pixels()
+            .map(|(point, _, _)| (point, point))
+            .reduce(|(min, max), (point, _)| (min.component_min(point), max.component_max(point)))
+            .map_or(Rectangle::zero(), |(min, max)| {
+                Rectangle::with_corners(min, max)
+            })
+    }
+
+    /// Get an iterator over every differing pixel as `(point, left, right)`, in row-major order.
+    pub fn pixels(&self) -> DiffPixels<'a, W, H, N, C> {
+        DiffPixels {
+            left: self.left,
+            right: self.right,
+            points: self.left.area.points(),
+        }
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> fmt::Debug for Diff<'_, W, H, N, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Diff").finish_non_exhaustive()
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> fmt::Display for Diff<'_, W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let area = self.bounding_box();
+
+        if area.is_zero_sized() {
+            return writeln!(f, "buffers are the same");
+        }
+
+        let count = self.count();
+
+        writeln!(
+            f,
+            "{} {} in the {}x{} area at ({}, {}) (left | right | differences):",
+            count,
+            if count == 1 {
+                "pixel differs"
+            } else {
+                "pixels differ"
+            },
+            area.size.width,
+            area.size.height,
+            area.top_left.x,
+            area.top_left.y
+        )?;
+
+        let raw = |buffer: &PackedBuffer<W, H, N, C>, point| {
+            VerticalByte::<W>::get_pixel::<C>(point, &buffer.buf)
+        };
+
+        for y in area.rows() {
+            let row = || area.columns().map(|x| Point::new(x, y));
+
+            for point in row() {
+                write_ascii::<C>(f, raw(self.left, point))?;
+            }
+
+            f.write_str(" | ")?;
+
+            for point in row() {
+                write_ascii::<C>(f, raw(self.right, point))?;
+            }
+
+            f.write_str(" | ")?;
+
+            for point in row() {
+                let differs = raw(self.left, point) != raw(self.right, point);
+
+                f.write_char(if differs { 'X' } else { '.' })?;
+            }
+
+            f.write_char('\n')?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An iterator over the differing pixels of two [`PackedBuffer`]s.
+///
+/// Created by [`Diff::pixels`].
+pub struct DiffPixels<'a, const W: u32, const H: u32, const N: usize, C> {
+    left: &'a PackedBuffer<W, H, N, C>,
+    right: &'a PackedBuffer<W, H, N, C>,
+    points: rectangle::Points,
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> fmt::Debug for DiffPixels<'_, W, H, N, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiffPixels")
+            .field("points", &self.points)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> Iterator for DiffPixels<'_, W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+{
+    type Item = (Point, C, C);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.points.find_map(|point| {
+            let left = VerticalByte::<W>::get_pixel::<C>(point, &self.left.buf);
+            let right = VerticalByte::<W>::get_pixel::<C>(point, &self.right.buf);
+
+            (left != right).then(|| (point, from_storage(left), from_storage(right)))
+        })
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+{
+    /// Compare the pixels of this buffer with another, which is shown on the right of the result.
+    ///
+    /// Only pixel values are compared. Active areas and raster operations are ignored.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Diff<'a, W, H, N, C> {
+        Diff {
+            left: self,
+            right: other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_size;
+    use embedded_graphics::pixelcolor::{BinaryColor, Gray4};
+    use embedded_graphics_core::{draw_target::DrawTarget, geometry::Dimensions};
+
+    type Display = PackedBuffer<8, 5, 8, BinaryColor>;
+
+    #[test]
+    fn same() {
+        let left = Display::from_art(
+            "
+            ........
+            ..##....
+            ..##....
+            ........
+            ........
+            ",
+        )
+        .unwrap();
+
+        let diff = left.diff(&left);
+
+        assert!(diff.is_empty());
+        assert_eq!(diff.count(), 0);
+        assert_eq!(diff.bounding_box(), Rectangle::zero());
+        assert_eq!(diff.pixels().next(), None);
+        assert_eq!(diff.to_string(), "buffers are the same\n");
+    }
+
+    #[test]
+    fn differences() {
+        let left = Display::from_art(
+            "
+            ........
+            ..##....
+            ..##....
+            ........
+            ........
+            ",
+        )
+        .unwrap();
+
+        let right = Display::from_art(
+            "
+            ........
+            ..##....
+            ...#....
+            .....#..
+            ........
+            ",
+        )
+        .unwrap();
+
+        let diff = left.diff(&right);
+
+        assert!(!diff.is_empty());
+        assert_eq!(diff.count(), 2);
+        assert_eq!(
+            diff.bounding_box(),
+            Rectangle::with_corners(Point::new(2, 2), Point::new(5, 3))
+        );
+        assert!(diff.pixels().eq([
+            (Point::new(2, 2), BinaryColor::On, BinaryColor::Off),
+            (Point::new(5, 3), BinaryColor::Off, BinaryColor::On),
+        ]));
+        assert_eq!(
+            diff.to_string(),
+            "2 pixels differ in the 4x2 area at (2, 2) (left | right | differences):\n\
+             ##.. | .#.. | X...\n\
+             .... | ...# | ...X\n"
+        );
+    }
+
+    #[test]
+    fn padding_is_ignored() {
+        let mut cleared = PackedBuffer::<8, 13, 16, BinaryColor>::new();
+        let mut filled = PackedBuffer::<8, 13, 16, BinaryColor>::new();
+
+        cleared.clear(BinaryColor::On).unwrap();
+        filled
+            .fill_solid(&filled.bounding_box(), BinaryColor::On)
+            .unwrap();
+
+        // The bits below the last row aren't pixels, so they don't count as differences
+        cleared.buf[8..].fill(0xff);
+
+        let diff = cleared.diff(&filled);
+
+        assert!(diff.is_empty(), "{}", diff);
+        assert_eq!(diff.count(), 0);
+        assert_eq!(diff.to_string(), "buffers are the same\n");
+    }
+
+    #[test]
+    fn gray() {
+        let mut left = PackedBuffer::<3, 3, { buffer_size::<Gray4>(3, 3) }, Gray4>::new();
+        let mut right = PackedBuffer::<3, 3, { buffer_size::<Gray4>(3, 3) }, Gray4>::new();
+
+        left.set_pixel(Point::new(1, 1), Gray4::new(0xa));
+        right.set_pixel(Point::new(1, 1), Gray4::new(0x3));
+
+        // Active areas don't count as differences
+        right.clear_active_area();
+        left.set_pixel(Point::new(2, 2), Gray4::new(0));
+
+        let diff = left.diff(&right);
+
+        assert_eq!(
+            diff.pixels().collect::<Vec<_>>(),
+            [(Point::new(1, 1), Gray4::new(0xa), Gray4::new(0x3))]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "1 pixel differs in the 1x1 area at (1, 1) (left | right | differences):\na | 3 | X\n"
+        );
+    }
+}
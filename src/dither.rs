@@ -0,0 +1,442 @@
+use crate::skip;
+use core::marker::PhantomData;
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, Point},
+    pixelcolor::{BinaryColor, Gray8, GrayColor, PixelColor},
+    primitives::{PointsIter, Rectangle},
+    Pixel,
+};
+
+/// 4x4 Bayer threshold matrix.
+const BAYER4: [[u8; 4]; 4] = [
+    [0, 8, 2, 10], //
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+/// 8x8 Bayer threshold matrix.
+const BAYER8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// `(dx, dy, weight)` error distribution for Floyd-Steinberg dithering, in 16ths.
+const FLOYD_STEINBERG: &[(i32, usize, i16)] = &[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)];
+
+/// `(dx, dy, weight)` error distribution for Atkinson dithering, in 8ths.
+///
+/// Only 6/8 of the error is propagated which gives higher contrast results than Floyd-Steinberg.
+const ATKINSON: &[(i32, usize, i16)] = &[
+    (1, 0, 1),
+    (2, 0, 1),
+    (-1, 1, 1),
+    (0, 1, 1),
+    (1, 1, 1),
+    (0, 2, 1),
+];
+
+/// `(dx, dy, weight)` error distribution and the divisor to apply to each weight.
+type Kernel = (&'static [(i32, usize, i16)], i16);
+
+/// Number of error rows required by the largest diffusion kernel.
+const ERROR_ROWS: usize = 3;
+
+/// Dithering algorithm used by [`Dither`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DitherMode {
+    /// Ordered dithering with a 4x4 Bayer matrix.
+    Bayer4,
+
+    /// Ordered dithering with an 8x8 Bayer matrix.
+    Bayer8,
+
+    /// Floyd-Steinberg error diffusion.
+    FloydSteinberg,
+
+    /// Atkinson error diffusion.
+    Atkinson,
+}
+
+impl DitherMode {
+    /// Ordered dither a single pixel at an absolute position.
+    fn ordered(self, point: Point, luma: u8) -> BinaryColor {
+        let x = point.x.rem_euclid(8) as usize;
+        let y = point.y.rem_euclid(8) as usize;
+
+        let (level, cells) = match self {
+            DitherMode::Bayer4 => (BAYER4[y % 4][x % 4], 16),
+            // Error diffusion can't be used for individual pixels as it requires them to be drawn
+            // in order, so fall back to the finer ordered pattern.
+            _ => (BAYER8[y][x], 64),
+        };
+
+        // Equivalent to `luma / 256 >= (level + 0.5) / cells`
+        let on = 2 * u32::from(luma) * cells >= (2 * u32::from(level) + 1) * 256;
+
+        on.into()
+    }
+
+    /// Error diffusion kernel and divisor, or `None` for ordered modes.
+    fn kernel(self) -> Option<Kernel> {
+        match self {
+            DitherMode::Bayer4 | DitherMode::Bayer8 => None,
+            DitherMode::FloydSteinberg => Some((FLOYD_STEINBERG, 16)),
+            DitherMode::Atkinson => Some((ATKINSON, 8)),
+        }
+    }
+}
+
+/// A draw target adapter that dithers grayscale or RGB colors into a 1bpp target such as a
+/// `PackedBuffer<W, H, N, BinaryColor>`.
+///
+/// Any color that can be converted into [`Gray8`] can be drawn, which includes all the RGB and
+/// grayscale colors in embedded-graphics.
+///
+/// Ordered modes dither against the absolute position of each pixel, so adjacent drawing
+/// operations line up seamlessly.
+///
+/// Error diffusion modes are applied to images and other areas drawn with `fill_contiguous` or
+/// `fill_solid`. Individual pixels drawn with `draw_iter` are dithered with [`DitherMode::Bayer8`]
+/// instead. Diffusion keeps a small error buffer of `E` columns per row, so memory use is bounded
+/// regardless of image size. `E` must be at least the width of the target to use a diffusion mode.
+///
+/// ```rust
+/// use embedded_graphics_core::{pixelcolor::{BinaryColor, Gray8}, prelude::*, primitives::Rectangle};
+/// use packed_display_buffer::{Dither, DitherMode, PackedBuffer};
+///
+/// let mut display = PackedBuffer::<128, 64, { 128 * 64 / 8 }, BinaryColor>::new();
+///
+/// let mut dither = Dither::<_, Gray8>::new(&mut display, DitherMode::FloydSteinberg);
+///
+/// dither.fill_solid(&Rectangle::new(Point::zero(), Size::new(16, 16)), Gray8::new(128))?;
+/// # Ok::<(), core::convert::Infallible>(())
+/// ```
+#[derive(Debug)]
+pub struct Dither<'a, T, C, const E: usize = 128> {
+    target: &'a mut T,
+    mode: DitherMode,
+    errors: [[i16; E]; ERROR_ROWS],
+    _color: PhantomData<C>,
+}
+
+impl<'a, T, C, const E: usize> Dither<'a, T, C, E>
+where
+    T: DrawTarget<Color = BinaryColor>,
+    C: PixelColor + Into<Gray8>,
+{
+    /// Create a dithering adapter for the given target.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mode` is an error diffusion mode and the target is wider than `E` pixels.
+    pub fn new(target: &'a mut T, mode: DitherMode) -> Self {
+        check_width::<E>(target, mode);
+
+        Self {
+            target,
+            mode,
+            errors: [[0; E]; ERROR_ROWS],
+            _color: PhantomData,
+        }
+    }
+
+    pub fn mode(&self) -> DitherMode {
+        self.mode
+    }
+
+    /// Change the dithering mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mode` is an error diffusion mode and the target is wider than `E` pixels.
+    pub fn set_mode(&mut self, mode: DitherMode) {
+        check_width::<E>(self.target, mode);
+
+        self.mode = mode;
+    }
+}
+
+/// Check that the error buffer is wide enough to diffuse across the whole target.
+fn check_width<const E: usize>(target: &impl Dimensions, mode: DitherMode) {
+    assert!(
+        mode.kernel().is_none() || target.bounding_box().size.width as usize <= E,
+        "Target is wider than the error buffer used for diffusion"
+    );
+}
+
+impl<T, C, const E: usize> Dimensions for Dither<'_, T, C, E>
+where
+    T: DrawTarget<Color = BinaryColor>,
+{
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+
+impl<T, C, const E: usize> DrawTarget for Dither<'_, T, C, E>
+where
+    T: DrawTarget<Color = BinaryColor>,
+    C: PixelColor + Into<Gray8>,
+{
+    type Color = C;
+    type Error = T::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let mode = self.mode;
+
+        self.target.draw_iter(
+            pixels
+                .into_iter()
+                .map(|Pixel(point, color)| Pixel(point, mode.ordered(point, luma(color)))),
+        )
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let mode = self.mode;
+
+        let visible = area.intersection(&self.target.bounding_box());
+
+        if visible.is_zero_sized() {
+            return Ok(());
+        }
+
+        let kernel = match mode.kernel() {
+            Some(kernel) => kernel,
+            None => {
+                let colors = area
+                    .points()
+                    .zip(colors)
+                    .map(|(point, color)| mode.ordered(point, luma(color)));
+
+                return self.target.fill_contiguous(area, colors);
+            }
+        };
+
+        let width = area.size.width as usize;
+        let visible_width = visible.size.width as usize;
+
+        let skip_left = (visible.top_left.x - area.top_left.x) as usize;
+        let skip_top = (visible.top_left.y - area.top_left.y) as usize;
+
+        let mut colors = colors.into_iter();
+        skip(&mut colors, skip_top * width);
+
+        self.errors = [[0; E]; ERROR_ROWS];
+
+        let diffusion = Diffusion {
+            colors,
+            errors: &mut self.errors,
+            kernel,
+            x: 0,
+            visible_width,
+            skip_left,
+            skip_right: width - skip_left - visible_width,
+        };
+
+        self.target.fill_contiguous(
+            &visible,
+            diffusion.take(visible_width * visible.size.height as usize),
+        )
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.fill_contiguous(
+            area,
+            core::iter::repeat_n(color, area.size.width as usize * area.size.height as usize),
+        )
+    }
+}
+
+fn luma<C: Into<Gray8>>(color: C) -> u8 {
+    color.into().luma()
+}
+
+/// Error diffusion over the visible part of a contiguous area, in row-major order.
+struct Diffusion<'e, I, const E: usize> {
+    colors: I,
+    errors: &'e mut [[i16; E]; ERROR_ROWS],
+    kernel: Kernel,
+    x: usize,
+    visible_width: usize,
+    skip_left: usize,
+    skip_right: usize,
+}
+
+impl<I, C, const E: usize> Iterator for Diffusion<'_, I, E>
+where
+    I: Iterator<Item = C>,
+    C: Into<Gray8>,
+{
+    type Item = BinaryColor;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.x == 0 {
+            skip(&mut self.colors, self.skip_left);
+        }
+
+        let value = i16::from(luma(self.colors.next()?)) + self.errors[0][self.x];
+
+        let on = value >= 128;
+        let error = value - if on { 255 } else { 0 };
+
+        let (weights, divisor) = self.kernel;
+
+        for &(dx, dy, weight) in weights {
+            let x = self.x as i32 + dx;
+
+            if (0..self.visible_width as i32).contains(&x) {
+                self.errors[dy][x as usize] += error * weight / divisor;
+            }
+        }
+
+        self.x += 1;
+
+        // Move on to the next row
+        if self.x == self.visible_width {
+            self.x = 0;
+
+            skip(&mut self.colors, self.skip_right);
+
+            self.errors.rotate_left(1);
+            self.errors[ERROR_ROWS - 1] = [0; E];
+        }
+
+        Some(on.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackedBuffer;
+    use embedded_graphics::pixelcolor::Rgb565;
+    use embedded_graphics_core::{geometry::Size, pixelcolor::RgbColor};
+
+    type Display = PackedBuffer<16, 16, { 16 * 16 / 8 }, BinaryColor>;
+
+    fn count_on(display: &Display) -> usize {
+        display
+            .pixels()
+            .filter(|Pixel(_, color)| color.is_on())
+            .count()
+    }
+
+    #[test]
+    fn ordered_half_gray() {
+        for mode in [DitherMode::Bayer4, DitherMode::Bayer8] {
+            let mut display = Display::new();
+
+            Dither::<_, Gray8>::new(&mut display, mode)
+                .clear(Gray8::new(128))
+                .unwrap();
+
+            assert_eq!(count_on(&display), 128, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn ordered_pattern_is_positional() {
+        let mut pixels = Display::new();
+        let mut fill = Display::new();
+
+        let area = Rectangle::new(Point::new(3, 5), Size::new(7, 6));
+
+        Dither::<_, Gray8>::new(&mut pixels, DitherMode::Bayer4)
+            .draw_iter(area.points().map(|p| Pixel(p, Gray8::new(90))))
+            .unwrap();
+
+        Dither::<_, Gray8>::new(&mut fill, DitherMode::Bayer4)
+            .fill_solid(&area, Gray8::new(90))
+            .unwrap();
+
+        let diff = pixels.diff(&fill);
+        assert!(diff.is_empty(), "{}", diff);
+    }
+
+    #[test]
+    fn diffusion_preserves_extremes() {
+        for mode in [DitherMode::FloydSteinberg, DitherMode::Atkinson] {
+            let mut display = Display::new();
+            let mut dither = Dither::<_, Rgb565>::new(&mut display, mode);
+
+            dither.clear(Rgb565::WHITE).unwrap();
+            dither
+                .fill_solid(
+                    &Rectangle::new(Point::new(4, 4), Size::new(8, 8)),
+                    Rgb565::BLACK,
+                )
+                .unwrap();
+
+            assert_eq!(count_on(&display), 256 - 64, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn floyd_steinberg_average() {
+        let mut display = Display::new();
+
+        Dither::<_, Gray8, 16>::new(&mut display, DitherMode::FloydSteinberg)
+            .clear(Gray8::new(64))
+            .unwrap();
+
+        // A quarter of the pixels should be lit, give or take some error at the edges
+        let on = count_on(&display);
+        assert!((56..=72).contains(&on), "{}", on);
+
+        // First row starts with no accumulated error
+        assert_eq!(display.get_pixel(Point::zero()), Some(BinaryColor::Off));
+    }
+
+    #[test]
+    fn diffusion_clipped() {
+        let mut display = Display::new();
+
+        let area = Rectangle::new(Point::new(-4, -4), Size::new(40, 8));
+
+        Dither::<_, Gray8, 16>::new(&mut display, DitherMode::Atkinson)
+            .fill_solid(&area, Gray8::WHITE)
+            .unwrap();
+
+        for Pixel(point, color) in display.pixels() {
+            assert_eq!(color.is_on(), point.y < 4, "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn narrow_error_buffer_allows_ordered() {
+        let mut display = Display::new();
+
+        let mut dither = Dither::<_, Gray8, 8>::new(&mut display, DitherMode::Bayer4);
+        dither.set_mode(DitherMode::Bayer8);
+    }
+
+    #[test]
+    #[should_panic(expected = "wider than the error buffer")]
+    fn too_wide_for_diffusion() {
+        let mut display = Display::new();
+
+        Dither::<_, Gray8, 8>::new(&mut display, DitherMode::FloydSteinberg);
+    }
+
+    #[test]
+    #[should_panic(expected = "wider than the error buffer")]
+    fn set_mode_too_wide_for_diffusion() {
+        let mut display = Display::new();
+
+        Dither::<_, Gray8, 8>::new(&mut display, DitherMode::Bayer8).set_mode(DitherMode::Atkinson);
+    }
+}
@@ -0,0 +1,562 @@
+use crate::PackedBuffer;
+use embedded_graphics_core::pixelcolor::BinaryColor;
+
+/// Error returned when an image can't be imported.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    /// The data isn't in a supported format.
+    UnsupportedFormat,
+
+    /// The image header is missing or malformed.
+    InvalidHeader,
+
+    /// The image dimensions don't match the buffer dimensions.
+    SizeMismatch {
+        /// Width of the image.
+        width: u32,
+
+        /// Height of the image.
+        height: u32,
+    },
+
+    /// The pixel data is truncated or contains invalid values.
+    InvalidData,
+}
+
+/// Reads through image data in a `const` context.
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    const fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    const fn peek(&self) -> Option<u8> {
+        if self.pos < self.data.len() {
+            Some(self.data[self.pos])
+        } else {
+            None
+        }
+    }
+
+    /// Skip whitespace, and comments running from the `comment` character to the end of the line.
+    const fn skip_whitespace(&mut self, comment: Option<u8>) {
+        while let Some(byte) = self.peek() {
+            if matches!(comment, Some(c) if c == byte) {
+                while let Some(byte) = self.peek() {
+                    if byte == b'\n' {
+                        break;
+                    }
+
+                    self.pos += 1;
+                }
+            } else if !byte.is_ascii_whitespace() {
+                break;
+            }
+
+            self.pos += 1;
+        }
+    }
+
+    /// Advance past the given pattern if the data starts with it.
+    const fn starts_with(&mut self, pattern: &[u8]) -> bool {
+        let mut i = 0;
+
+        while i < pattern.len() {
+            if self.pos + i >= self.data.len() || self.data[self.pos + i] != pattern[i] {
+                return false;
+            }
+
+            i += 1;
+        }
+
+        self.pos += pattern.len();
+
+        true
+    }
+
+    /// Skip spaces and tabs, but not newlines.
+    const fn skip_blanks(&mut self) {
+        while let Some(b' ' | b'\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// Advance to the start of the next line.
+    const fn skip_line(&mut self) {
+        while let Some(byte) = self.peek() {
+            self.pos += 1;
+
+            if byte == b'\n' {
+                break;
+            }
+        }
+    }
+
+    /// Advance to the value of the next `#define <name><suffix> <value>` line.
+    ///
+    /// Only lines starting with `#define` are considered, so the suffix isn't matched inside
+    /// comments or other identifiers. Returns `false` if there's no matching line.
+    const fn define(&mut self, suffix: &[u8]) -> bool {
+        while self.pos < self.data.len() {
+            self.skip_blanks();
+
+            if self.starts_with(b"#define") && matches!(self.peek(), Some(b' ' | b'\t')) {
+                self.skip_blanks();
+
+                let start = self.pos;
+
+                while let Some(byte) = self.peek() {
+                    if !(byte.is_ascii_alphanumeric() || byte == b'_') {
+                        break;
+                    }
+
+                    self.pos += 1;
+                }
+
+                if self.pos - start > suffix.len()
+                    && self.ends_with(suffix)
+                    && matches!(self.peek(), Some(b' ' | b'\t'))
+                {
+                    self.skip_blanks();
+
+                    return true;
+                }
+            }
+
+            self.skip_line();
+        }
+
+        false
+    }
+
+    /// Whether the data before the cursor ends with the given pattern.
+    const fn ends_with(&self, pattern: &[u8]) -> bool {
+        if self.pos < pattern.len() {
+            return false;
+        }
+
+        let start = self.pos - pattern.len();
+
+        let mut i = 0;
+        while i < pattern.len() {
+            if self.data[start + i] != pattern[i] {
+                return false;
+            }
+
+            i += 1;
+        }
+
+        true
+    }
+
+    /// Advance past the next occurrence of the given pattern.
+    const fn find(&mut self, pattern: &[u8]) -> bool {
+        while self.pos < self.data.len() {
+            if self.starts_with(pattern) {
+                return true;
+            }
+
+            self.pos += 1;
+        }
+
+        false
+    }
+
+    /// Parse a decimal, or `0x` prefixed hexadecimal, number.
+    const fn number(&mut self) -> Option<u32> {
+        let radix = if self.starts_with(b"0x") || self.starts_with(b"0X") {
+            16
+        } else {
+            10
+        };
+
+        let start = self.pos;
+        let mut value: u32 = 0;
+
+        while let Some(byte) = self.peek() {
+            let digit = match (byte as char).to_digit(radix) {
+                Some(digit) => digit,
+                None => break,
+            };
+
+            value = match value.checked_mul(radix) {
+                Some(value) => match value.checked_add(digit) {
+                    Some(value) => value,
+                    None => return None,
+                },
+                None => return None,
+            };
+
+            self.pos += 1;
+        }
+
+        if self.pos == start {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize> PackedBuffer<W, H, N, BinaryColor> {
+    /// Decode a PBM image (plain `P1` or binary `P4`) into a buffer.
+    ///
+    /// The image must be exactly `W` x `H` pixels. Rows are repacked into vertical pages as they're
+    /// read, so the result can be blitted straight into another buffer with
+    /// [`blit`](Self::blit).
+    ///
+    /// PBM stores black as `1`. To match [`write_pnm`](Self::write_pnm), black pixels are imported
+    /// as [`BinaryColor::Off`] and white pixels as [`BinaryColor::On`], so the image looks the same
+    /// on an OLED panel as it does in an image viewer.
+    ///
+    /// This is a `const fn`, so images can be decoded at compile time:
+    ///
+    /// ```rust
+    /// use embedded_graphics_core::{pixelcolor::BinaryColor, prelude::*};
+    /// use packed_display_buffer::PackedBuffer;
+    ///
+    /// // Usually `include_bytes!("icon.pbm")`
+    /// const ICON: PackedBuffer<3, 2, 3, BinaryColor> =
+    ///     match PackedBuffer::from_pbm(b"P1\n3 2\n010\n101\n") {
+    ///         Ok(icon) => icon,
+    ///         Err(_) => panic!("Invalid icon"),
+    ///     };
+    ///
+    /// assert_eq!(ICON.get_pixel(Point::new(0, 0)), Some(BinaryColor::On));
+    /// assert_eq!(ICON.get_pixel(Point::new(1, 0)), Some(BinaryColor::Off));
+    /// ```
+    pub const fn from_pbm(data: &[u8]) -> Result<Self, ImportError> {
+        let mut cursor = Cursor::new(data);
+
+        let binary = if cursor.starts_with(b"P1") {
+            false
+        } else if cursor.starts_with(b"P4") {
+            true
+        } else {
+            return Err(ImportError::UnsupportedFormat);
+        };
+
+        let (width, height) = match Self::pbm_size(&mut cursor) {
+            Some(size) => size,
+            None => return Err(ImportError::InvalidHeader),
+        };
+
+        if width != W || height != H {
+            return Err(ImportError::SizeMismatch { width, height });
+        }
+
+        let mut buffer = Self::new();
+
+        if binary {
+            // A single whitespace character separates the header from the data
+            match cursor.peek() {
+                Some(byte) if byte.is_ascii_whitespace() => cursor.pos += 1,
+                _ => return Err(ImportError::InvalidHeader),
+            }
+
+            let row_bytes = W.div_ceil(u8::BITS) as usize;
+
+            if cursor.pos + row_bytes * H as usize > data.len() {
+                return Err(ImportError::InvalidData);
+            }
+
+            let mut y = 0;
+            while y < H {
+                let mut x = 0;
+                while x < W {
+                    let byte = data[cursor.pos + y as usize * row_bytes + (x / u8::BITS) as usize];
+
+                    // MSB is the leftmost pixel, and 0 is white
+                    buffer.import_pixel(x, y, byte & (0x80 >> (x % u8::BITS)) == 0);
+
+                    x += 1;
+                }
+
+                y += 1;
+            }
+        } else {
+            let mut i = 0;
+            while i < W * H {
+                cursor.skip_whitespace(Some(b'#'));
+
+                let lit = match cursor.peek() {
+                    Some(b'0') => true,
+                    Some(b'1') => false,
+                    _ => return Err(ImportError::InvalidData),
+                };
+
+                buffer.import_pixel(i % W, i / W, lit);
+
+                cursor.pos += 1;
+                i += 1;
+            }
+        }
+
+        Ok(buffer)
+    }
+
+    /// Decode an XBM image into a buffer.
+    ///
+    /// XBM images are C source code, declaring the size with `#define` lines followed by an array
+    /// of bytes. The image must be exactly `W` x `H` pixels. Rows are repacked into vertical pages
+    /// as they're read, so the result can be blitted straight into another buffer with
+    /// [`blit`](Self::blit).
+    ///
+    /// Set bits are drawn black in an image viewer, so like [`from_pbm`](Self::from_pbm) they're
+    /// imported as [`BinaryColor::Off`] and clear bits as [`BinaryColor::On`]. The same drawing
+    /// imports identically from either format.
+    ///
+    /// Like [`from_pbm`](Self::from_pbm), this is a `const fn`.
+    pub const fn from_xbm(data: &[u8]) -> Result<Self, ImportError> {
+        let mut cursor = Cursor::new(data);
+
+        if !cursor.define(b"_width") {
+            return Err(ImportError::UnsupportedFormat);
+        }
+
+        let width = cursor.number();
+
+        if !cursor.define(b"_height") {
+            return Err(ImportError::InvalidHeader);
+        }
+
+        let height = cursor.number();
+
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Err(ImportError::InvalidHeader),
+        };
+
+        if width != W || height != H {
+            return Err(ImportError::SizeMismatch { width, height });
+        }
+
+        if !cursor.find(b"{") {
+            return Err(ImportError::InvalidData);
+        }
+
+        let mut buffer = Self::new();
+
+        let row_bytes = W.div_ceil(u8::BITS);
+
+        let mut i = 0;
+        while i < row_bytes * H {
+            cursor.skip_whitespace(None);
+
+            let byte = match cursor.number() {
+                Some(byte) if byte <= u8::MAX as u32 => byte as u8,
+                _ => return Err(ImportError::InvalidData),
+            };
+
+            cursor.skip_whitespace(None);
+
+            if !cursor.starts_with(b",") && !matches!(cursor.peek(), Some(b'}')) {
+                return Err(ImportError::InvalidData);
+            }
+
+            let y = i / row_bytes;
+            let x_start = (i % row_bytes) * u8::BITS;
+
+            // LSB is the leftmost pixel, and 0 is white
+            let mut bit = 0;
+            while bit < u8::BITS && x_start + bit < W {
+                buffer.import_pixel(x_start + bit, y, byte & (1 << bit) == 0);
+
+                bit += 1;
+            }
+
+            i += 1;
+        }
+
+        Ok(buffer)
+    }
+
+    /// Read the width and height from a PBM header.
+    const fn pbm_size(cursor: &mut Cursor<'_>) -> Option<(u32, u32)> {
+        cursor.skip_whitespace(Some(b'#'));
+        let width = cursor.number();
+
+        cursor.skip_whitespace(Some(b'#'));
+        let height = cursor.number();
+
+        match (width, height) {
+            (Some(width), Some(height)) => Some((width, height)),
+            _ => None,
+        }
+    }
+
+    /// Set a pixel in a freshly created buffer.
+    const fn import_pixel(&mut self, x: u32, y: u32, lit: bool) {
+        if lit {
+            self.buf[((y / u8::BITS) * W + x) as usize] |= 1 << (y % u8::BITS);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer_size, SliceSink};
+    use embedded_graphics_core::{
+        geometry::{Dimensions, Point},
+        primitives::{PointsIter, Rectangle},
+        Pixel,
+    };
+
+    type Icon = PackedBuffer<10, 11, { buffer_size::<BinaryColor>(10, 11) }, BinaryColor>;
+
+    fn pattern(point: Point) -> BinaryColor {
+        ((point.x + point.y * 3) % 4 == 0 || point.y == 9).into()
+    }
+
+    #[test]
+    fn pbm_round_trip() {
+        let mut icon = Icon::new();
+
+        for point in icon.bounding_box().points() {
+            icon.set_pixel(point, pattern(point));
+        }
+
+        let mut buf = [0u8; 64];
+        let mut sink = SliceSink::new(&mut buf);
+        icon.write_pnm(&mut sink).unwrap();
+
+        let imported = Icon::from_pbm(sink.written()).unwrap();
+        assert_eq!(imported.as_ref(), icon.as_ref());
+
+        let mut ascii = String::new();
+        icon.write_pnm_ascii(&mut ascii).unwrap();
+
+        let imported = Icon::from_pbm(ascii.as_bytes()).unwrap();
+        assert_eq!(imported.as_ref(), icon.as_ref());
+    }
+
+    #[test]
+    fn pbm_comments() {
+        let data = b"P1\n# An icon\n3 # width\n2\n0 1 0\n1 0 # trailing\n 1\n";
+
+        let icon = PackedBuffer::<3, 2, 3, BinaryColor>::from_pbm(data).unwrap();
+
+        assert_eq!(icon.as_ref(), &[0b01, 0b10, 0b01]);
+    }
+
+    #[test]
+    fn xbm() {
+        let data = br#"
+            #define icon_width 10
+            #define icon_height 3
+            static unsigned char icon_bits[] = {
+               0x01, 0x02, 0xff, 0x03,
+               0x00, 0x00 };
+        "#;
+
+        let icon = PackedBuffer::<10, 3, 10, BinaryColor>::from_xbm(data).unwrap();
+
+        // Set bits are black, so they're off
+        let expected = [
+            "#........#", //
+            "##########", //
+            "..........", //
+        ];
+
+        for Pixel(point, color) in icon.pixels() {
+            let row = expected[point.y as usize].as_bytes();
+
+            assert_eq!(color, (row[point.x as usize] == b'.').into(), "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn pbm_and_xbm_match() {
+        // The same 10x3 drawing in both formats, with black pixels set
+        let pbm = b"P1\n10 3\n1000000001\n1111111111\n0000000000\n";
+        let xbm = b"#define icon_width 10\n#define icon_height 3\n\
+            static unsigned char icon_bits[] = { 0x01, 0x02, 0xff, 0x03, 0x00, 0x00 };";
+
+        let from_pbm = PackedBuffer::<10, 3, 10, BinaryColor>::from_pbm(pbm).unwrap();
+        let from_xbm = PackedBuffer::<10, 3, 10, BinaryColor>::from_xbm(xbm).unwrap();
+
+        assert_eq!(from_pbm.as_ref(), from_xbm.as_ref());
+        assert_eq!(from_pbm.get_pixel(Point::new(0, 0)), Some(BinaryColor::Off));
+    }
+
+    #[test]
+    fn xbm_defines() {
+        // Sizes are only read from `#define` lines, not comments or other identifiers
+        let data = b"/* icon_width 99, icon_height 99 */\n\
+            #define icon_x_hot_width_ignored\n\
+            \t#define icon_width 3\n\
+            #define icon_height 2\n\
+            static char icon_bits[] = { 0, 0 };";
+
+        let icon = PackedBuffer::<3, 2, 3, BinaryColor>::from_xbm(data).unwrap();
+        assert_eq!(icon.as_ref(), &[0b11; 3]);
+
+        assert_eq!(
+            PackedBuffer::<3, 2, 3, BinaryColor>::from_xbm(b"int icon_width = 3;\n"),
+            Err(ImportError::UnsupportedFormat)
+        );
+    }
+
+    #[test]
+    fn const_import() {
+        const ICON: PackedBuffer<2, 9, 4, BinaryColor> = match PackedBuffer::from_xbm(
+            b"#define a_width 2\n#define a_height 9\n{1,2,1,2,1,2,1,2,3}",
+        ) {
+            Ok(icon) => icon,
+            Err(_) => panic!(),
+        };
+
+        assert_eq!(ICON.as_ref(), &[0xaa, 0x55, 0x00, 0x00]);
+
+        // Imported sprites can be blitted without iterating over pixels
+        let mut display = PackedBuffer::<8, 16, 16, BinaryColor>::new();
+        display.blit(ICON.as_slice(), &ICON.bounding_box(), Point::new(3, 4));
+
+        assert_eq!(
+            display.active_area(),
+            Rectangle::new(Point::new(3, 4), ICON.bounding_box().size)
+        );
+        assert_eq!(display.get_pixel(Point::new(3, 4)), Some(BinaryColor::Off));
+        assert_eq!(display.get_pixel(Point::new(4, 4)), Some(BinaryColor::On));
+    }
+
+    #[test]
+    fn errors() {
+        type Small = PackedBuffer<3, 2, 3, BinaryColor>;
+
+        assert_eq!(
+            Small::from_pbm(b"P2\n3 2\n"),
+            Err(ImportError::UnsupportedFormat)
+        );
+        assert_eq!(Small::from_pbm(b"P1\n3"), Err(ImportError::InvalidHeader));
+        assert_eq!(
+            Small::from_pbm(b"P1\n4 2\n"),
+            Err(ImportError::SizeMismatch {
+                width: 4,
+                height: 2
+            })
+        );
+        assert_eq!(
+            Small::from_pbm(b"P1\n3 2\n0 1 0 1"),
+            Err(ImportError::InvalidData)
+        );
+        assert_eq!(
+            Small::from_pbm(b"P4\n3 2\n\x00"),
+            Err(ImportError::InvalidData)
+        );
+        assert_eq!(
+            Small::from_pbm(b"P4\n3 2x\x00\x00"),
+            Err(ImportError::InvalidHeader)
+        );
+        assert_eq!(
+            Small::from_xbm(b"#define a_width 3\n#define a_height 2\n{ 0x100, 0 }"),
+            Err(ImportError::InvalidData)
+        );
+        assert_eq!(Small::from_xbm(b"P1"), Err(ImportError::UnsupportedFormat));
+    }
+}
@@ -1,24 +1,93 @@
-// #![no_std]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
 use active_area::ActiveArea;
 use block_iterator::BlockIterator;
+use byte_direction::{ByteDirection, VerticalByte};
 use core::{convert::Infallible, marker::PhantomData};
 use embedded_graphics_core::{
     draw_target::DrawTarget,
     geometry::{OriginDimensions, Point, Size},
-    pixelcolor::{raw::RawData, BinaryColor, IntoStorage, PixelColor},
+    pixelcolor::{raw::RawData, IntoStorage, PixelColor},
     primitives::Rectangle,
     Pixel,
 };
-use mask::StartChunk;
-// use mask::StartChunk;
-// use pixels::Pixels;
+use mask::{slot_bits, StartChunk};
+use merge::merge;
 
 mod active_area;
+mod art;
+mod bitplane;
+mod blit;
 mod block_iterator;
 mod byte_direction;
+mod color_buffer;
+mod diff;
+mod dither;
+mod import;
+mod line;
 mod mask;
-// mod pixels;
+#[doc(hidden)]
+pub mod merge;
+pub mod mirror;
+mod palette;
+mod pixels;
+mod pnm;
+mod raster_op;
+pub mod rle;
+mod scroll;
+#[cfg(feature = "simulator")]
+pub mod simulator;
+pub mod snapshot;
+mod sub_target;
+
+pub use art::{Art, ArtError, ArtStyle};
+pub use bitplane::BitplaneBuffer;
+pub use blit::PackedSlice;
+pub use byte_direction::Layout;
+pub use color_buffer::{BigEndian, ColorBuffer, Endianness, LittleEndian};
+pub use diff::{Diff, DiffPixels};
+pub use dither::{Dither, DitherMode};
+pub use import::ImportError;
+pub use palette::{ColorDistance, Palette, PaletteBuffer};
+pub use pixels::Pixels;
+#[cfg(any(test, feature = "std"))]
+pub use pnm::IoSink;
+pub use pnm::{write_pnm, write_pnm_ascii, BufferFull, ByteSink, SliceSink};
+pub use raster_op::RasterOp;
+pub use rle::RleError;
+pub use snapshot::SnapshotError;
+pub use sub_target::SubTarget;
+
+/// Compute the number of bytes required to store a `width` x `height` display of color `C`.
+///
+/// For colors up to 8bpp, pixels are packed into vertical pages as in [`PackedBuffer`]. Heights that
+/// don't fill a whole page are rounded up to the next page.
+///
+/// Wider colors are stored as whole bytes per pixel as in [`ColorBuffer`].
+///
+/// This can be used to compute `N` for both buffer types:
+///
+/// ```rust
+/// use embedded_graphics_core::pixelcolor::{Gray2, Rgb565};
+/// use packed_display_buffer::{buffer_size, ColorBuffer, PackedBuffer};
+///
+/// let buffer = PackedBuffer::<128, 60, { buffer_size::<Gray2>(128, 60) }, Gray2>::new();
+/// let buffer = ColorBuffer::<320, 240, { buffer_size::<Rgb565>(320, 240) }, Rgb565>::new();
+/// ```
+pub const fn buffer_size<C>(width: u32, height: u32) -> usize
+where
+    C: PixelColor,
+{
+    let bpp = C::Raw::BITS_PER_PIXEL;
+
+    if bpp > u8::BITS as usize {
+        return width as usize * height as usize * bpp.div_ceil(u8::BITS as usize);
+    }
+
+    let page_bits = height * slot_bits(bpp);
+
+    (width * page_bits.div_ceil(u8::BITS)) as usize
+}
 
 // TODO: Remove `N` and calculate from W * H when const features allow us to do so.
 #[derive(Debug, PartialEq)]
@@ -26,6 +95,7 @@ pub struct PackedBuffer<const W: u32, const H: u32, const N: usize, C> {
     buf: [u8; N],
     area: Rectangle,
     active_area: ActiveArea<W, H>,
+    op: RasterOp,
     _color: PhantomData<C>,
 }
 
@@ -33,31 +103,45 @@ impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
 where
     C: PixelColor + IntoStorage<Storage = u8>,
 {
-    pub const fn new() -> Self {
-        // TODO: Remove this when we can do maths in const generics
-        // FIXME: What if H is not a multiple of 8 high?
-        if N != (W * H / u8::BITS) as usize {
-            panic!("Invariant error: W * H != N")
-        }
+    /// Number of bits each pixel occupies in the buffer.
+    const SLOT_BITS: u32 = slot_bits(C::Raw::BITS_PER_PIXEL);
 
+    pub const fn new() -> Self {
         assert!(
-            C::Raw::BITS_PER_PIXEL <= 8,
-            "Only pixel formats with <=8bpp are currently supported"
+            C::Raw::BITS_PER_PIXEL > 0 && C::Raw::BITS_PER_PIXEL <= 8,
+            "Only pixel formats with 1 to 8bpp are currently supported"
         );
 
+        // TODO: Remove this when we can do maths in const generics
+        if N != buffer_size::<C>(W, H) {
+            panic!("Invariant error: N != buffer_size(W, H)")
+        }
+
         Self {
             buf: [0x00u8; N],
             area: Rectangle::new(Point::zero(), Size::new(W, H)),
             active_area: ActiveArea::new(),
+            op: RasterOp::Replace,
             _color: PhantomData,
         }
     }
 
+    /// Set the raster operation used to combine new pixels with the existing buffer contents.
+    ///
+    /// This applies to all drawing operations, including individual pixels and filled areas.
+    pub fn set_raster_op(&mut self, op: RasterOp) {
+        self.op = op;
+    }
+
+    pub fn raster_op(&self) -> RasterOp {
+        self.op
+    }
+
     /// Set an individual pixel.
     ///
     /// Any given pixels that are outside the display area will be ignored.
-    pub fn set_pixel(&mut self, point: Point, color: BinaryColor) {
-        // Invariant: requires W * H == N / 8
+    pub fn set_pixel(&mut self, point: Point, color: C) {
+        // Invariant: requires N == buffer_size(W, H)
         if !self.area.contains(point) {
             return;
         }
@@ -67,23 +151,10 @@ where
         self.active_area.update_from_point(point);
     }
 
-    fn set_pixel_unchecked(&mut self, point: Point, color: BinaryColor) {
-        let color = color as u8;
-        let Point { x, y } = point;
-
-        let idx = ((y as usize) / u8::BITS as usize * W as usize) + (x as usize);
-        let bit = y % u8::BITS as i32;
-
-        let byte = &mut self.buf[idx];
-
-        *byte = *byte & !(1 << bit) | (color << bit)
+    fn set_pixel_unchecked(&mut self, point: Point, color: C) {
+        VerticalByte::<W>::merge_pixel(point, color, self.op, &mut self.buf);
     }
 
-    // TODO
-    // pub fn pixels<'a>(&'a self) -> Pixels<'a> {
-    //     Pixels::new(self)
-    // }
-
     /// Create a range representing the indices corresponding to the section of a block in the given
     /// area.
     ///
@@ -113,28 +184,36 @@ where
 
     /// Fill a packed buffer with the given color in the given area.
     ///
-    /// The area is clipped to the display dimensions. In conjunction with the `W * H = N` assertion
-    /// in [`new`] guarantees that no out of bounds writes can occur.
+    /// The area is clipped to the display dimensions. In conjunction with the `N == buffer_size(W,
+    /// H)` assertion in [`new`] guarantees that no out of bounds writes can occur.
     fn fill_rect(&mut self, rect: &Rectangle, color: C) {
-        let color: u8 = color.into_storage();
-
         // Repeat color throughout the byte
-        let color = match C::Raw::BITS_PER_PIXEL {
-            1 => {
-                if color > 0 {
-                    0xff
-                } else {
-                    0x00
-                }
-            }
-            2 => color | color << 2 | color << 4 | color << 6,
-            4 => color | color << 4,
-            8 => color,
-        };
+        let pattern = mask::repeat(color.into_storage(), Self::SLOT_BITS);
 
-        let rect = rect.intersection(&self.area);
+        self.fill_pattern(rect, pattern, self.op);
+    }
+
+    /// Invert every pixel in the given area.
+    ///
+    /// The area is clipped to the display dimensions. This is much faster than reading back and
+    /// rewriting individual pixels as whole blocks are XORed at once. The current raster operation
+    /// is ignored.
+    ///
+    /// For grayscale colors each pixel value `v` is replaced with `max - v`.
+    pub fn invert_rect(&mut self, rect: &Rectangle) {
+        let pattern = mask::repeat(
+            mask::slot_mask(C::Raw::BITS_PER_PIXEL as u32),
+            Self::SLOT_BITS,
+        );
 
-        let y_start = rect.top_left.y as u32;
+        self.fill_pattern(rect, pattern, RasterOp::Xor);
+    }
+
+    /// Combine every byte in the given area with `pattern` using the given raster operation.
+    ///
+    /// The area is clipped to the display dimensions. Only the bits inside the area are modified.
+    fn fill_pattern(&mut self, rect: &Rectangle, pattern: u8, op: RasterOp) {
+        let rect = rect.intersection(&self.area);
 
         let br = if let Some(br) = rect.bottom_right() {
             br
@@ -145,19 +224,20 @@ where
 
         self.active_area.update_from_rect(rect);
 
-        let y_end = br.y as u32;
+        // Rows are converted to bit offsets in each column so that wider pixels are handled the
+        // same way as 1bpp ones.
+        let bit_start = rect.top_left.y as u32 * Self::SLOT_BITS;
+        let bit_end = (br.y as u32 + 1) * Self::SLOT_BITS - 1;
 
-        let mut block = rect.top_left.y as usize / u8::BITS as usize;
+        let mut block = (bit_start / u8::BITS) as usize;
 
         let StartChunk {
             mask: first_mask,
             mut remaining,
-        } = mask::start_chunk(y_start, y_end);
+        } = mask::start_chunk(bit_start, bit_end);
 
         // If the area covers part of a block, merge the top row with existing data in the block
-        self.block_range(block, &rect)
-            .iter_mut()
-            .for_each(|byte| *byte = (*byte & !first_mask) | (color & first_mask));
+        merge(self.block_range(block, &rect), pattern, first_mask, op);
 
         // If fill rectangle fits entirely within first block, there's nothing more to do
         if remaining == 0 {
@@ -167,11 +247,16 @@ where
         // Start filling blocks below the starting partial block
         block += 1;
 
-        // Completely fill middle blocks in the area. We don't need to do any bit twiddling here so
-        // it can be optimised by just filling the slice
+        // Completely fill middle blocks in the area. When replacing we don't need to do any bit
+        // twiddling here so it can be optimised by just filling the slice
         while remaining >= u8::BITS {
-            // Completely overwrite any existing value
-            self.block_range(block, &rect).fill(u8::MAX);
+            let range = self.block_range(block, &rect);
+
+            match op {
+                // Completely overwrite any existing value
+                RasterOp::Replace => range.fill(pattern),
+                op => merge(range, pattern, u8::MAX, op),
+            }
 
             block += 1;
             remaining -= u8::BITS;
@@ -180,63 +265,83 @@ where
         // Partially fill end block if there are any remaining bits
         if remaining > 0 {
             // Merge block underneath last fully filled block with current data
-            self.block_range(block, &rect).iter_mut().for_each(|byte| {
-                let mask = !(i8::MAX << remaining) as u8;
+            let mask = !(i8::MAX << remaining) as u8;
 
-                // Merge with existing data
-                *byte = (*byte & !mask) | (color & mask)
-            });
+            merge(self.block_range(block, &rect), pattern, mask, op);
         }
     }
 
-    /// Contiguous fill.
-    fn fill_rect_iter<I>(&mut self, rect: &Rectangle, colors: I)
+    /// Fill the given area with colors from an iterator, in row-major order.
+    ///
+    /// The area is clipped to `clip` and the display dimensions, with colors for pixels outside
+    /// either skipped.
+    ///
+    /// When replacing pixels, each row is walked along its page's bytes and every byte is masked
+    /// to the row's slot, so a byte is read and written once for each row of the page it holds.
+    /// This avoids locating every pixel individually, and pixels the iterator runs out before
+    /// reaching are left untouched. Other raster operations merge each pixel individually.
+    fn fill_rect_iter<I>(&mut self, rect: &Rectangle, clip: &Rectangle, colors: I)
     where
-        I: IntoIterator<Item = BinaryColor>,
+        I: IntoIterator<Item = C>,
     {
-        // let intersection = rect.intersection(&self.area);
+        let intersection = rect.intersection(&clip.intersection(&self.area));
+
+        if intersection.is_zero_sized() {
+            // Don't draw anything if the entire rect lies outside the visible area
+            return;
+        }
+
+        self.active_area.update_from_rect(intersection);
+
+        let mut colors = colors.into_iter();
+
+        let width = rect.size.width as usize;
+        let visible_width = intersection.size.width as usize;
 
-        // // Don't draw anything if the entire rect lies outside the visible area
-        // if intersection.is_zero_sized() {
-        //     return;
-        // }
+        // Pixels to skip at the start and end of each row
+        let skip_left = (intersection.top_left.x - rect.top_left.x) as usize;
+        let skip_right = width - skip_left - visible_width;
 
-        // self.active_area.update_from_rect(intersection);
+        // Rows above the visible area
+        let skip_top = (intersection.top_left.y - rect.top_left.y) as usize;
+        skip(&mut colors, skip_top * width);
 
-        // // Number of lines above the visible area
-        // let row_pre_skip = rect.top_left.y.min(0).abs() as u32;
+        let op = self.op;
 
-        // // Number of pixels above the visible area
-        // let skip = row_pre_skip * rect.size.width;
+        if op != RasterOp::Replace {
+            for y in intersection.rows() {
+                skip(&mut colors, skip_left);
 
-        // // Take only the whole rows within the visible area. This filters out rows below the visible
-        // // area
-        // let take = intersection.size.height * rect.size.width;
+                for (x, color) in intersection.columns().zip(colors.by_ref()) {
+                    self.set_pixel_unchecked(Point::new(x, y), color);
+                }
 
-        // let colors = colors
-        //     .into_iter()
-        //     .skip(skip as usize)
-        //     .take(take as usize)
-        //     .enumerate();
+                skip(&mut colors, skip_right);
+            }
 
-        // let x_range = 0..W as i32;
+            return;
+        }
 
-        // for (idx, color) in colors {
-        //     let idx = idx as u32;
-        //     let x = rect.top_left.x + (idx % rect.size.width) as i32;
-        //     let y = intersection.top_left.y + (idx / rect.size.width) as i32;
+        let slot_mask = mask::slot_mask(Self::SLOT_BITS);
 
-        //     // We checked Y range before with .skip().take() on the iterator. We only need to check
-        //     // whether the X coordinate is within the visible area here.
-        //     if !x_range.contains(&x) {
-        //         continue;
-        //     }
+        for y in intersection.rows() {
+            skip(&mut colors, skip_left);
 
-        //     let pos = Point::new(x, y);
+            let bit = y as u32 * Self::SLOT_BITS;
+            let shift = bit % u8::BITS;
 
-        //     self.set_pixel_unchecked(pos, color);
-        // }
-        todo!()
+            // Only this row's bits are replaced, so rows the iterator never reaches keep their
+            // existing values
+            let clear = slot_mask << shift;
+
+            let row = self.block_range((bit / u8::BITS) as usize, &intersection);
+
+            for (byte, color) in row.iter_mut().zip(colors.by_ref()) {
+                *byte = (*byte & !clear) | color.into_storage() << shift;
+            }
+
+            skip(&mut colors, skip_right);
+        }
     }
 
     pub fn clear_active_area(&mut self) {
@@ -268,19 +373,27 @@ where
     /// This method can be useful for efficiently transferring contiguous parts of the display
     /// buffer to the display hardware.
     pub fn active_blocks<'a>(&'a self) -> BlockIterator<'a> {
-        let active_area = self.active_area.rectangle();
+        self.blocks(&self.active_area.rectangle())
+    }
 
-        let br = if let Some(br) = active_area.bottom_right() {
+    /// Get an iterator over blocks covering the given area of the display buffer.
+    ///
+    /// The area is clipped to the display dimensions. Like [`active_blocks`](Self::active_blocks),
+    /// each block spans the whole height of every page touched by the area.
+    pub fn blocks<'a>(&'a self, area: &Rectangle) -> BlockIterator<'a> {
+        let area = area.intersection(&self.area);
+
+        let br = if let Some(br) = area.bottom_right() {
             br
         } else {
             return BlockIterator::empty();
         };
 
-        let start_block = active_area.top_left.y as u32 / 8;
-        let end_block = br.y as u32 / 8 + 1;
+        let start_block = area.top_left.y as u32 * Self::SLOT_BITS / u8::BITS;
+        let end_block = ((br.y as u32 + 1) * Self::SLOT_BITS - 1) / u8::BITS + 1;
 
-        let start_idx = (start_block * W) + active_area.top_left.x as u32;
-        let block_width = active_area.size.width;
+        let start_idx = (start_block * W) + area.top_left.x as u32;
+        let block_width = area.size.width;
 
         BlockIterator {
             buffer: &self.buf,
@@ -293,6 +406,45 @@ where
     }
 }
 
+impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+{
+    /// Get the color of an individual pixel.
+    ///
+    /// Returns `None` if the point is outside the display area.
+    pub fn get_pixel(&self, point: Point) -> Option<C> {
+        self.area
+            .contains(point)
+            .then(|| self.get_pixel_unchecked(point))
+    }
+
+    fn get_pixel_unchecked(&self, point: Point) -> C {
+        pixels::from_storage(VerticalByte::<W>::get_pixel::<C>(point, &self.buf))
+    }
+
+    /// Get an iterator over every pixel in the buffer, in row-major order.
+    pub fn pixels(&self) -> Pixels<'_, W, H, N, C> {
+        Pixels::new(self)
+    }
+}
+
+/// Advance an iterator by `n` items.
+pub(crate) fn skip<I: Iterator>(iter: &mut I, n: usize) {
+    if n > 0 {
+        iter.nth(n - 1);
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> Default for PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<const W: u32, const H: u32, const N: usize, C> AsRef<[u8]> for PackedBuffer<W, H, N, C> {
     fn as_ref(&self) -> &[u8] {
         &self.buf
@@ -307,10 +459,9 @@ impl<const W: u32, const H: u32, const N: usize, C> OriginDimensions for PackedB
 
 impl<const W: u32, const H: u32, const N: usize, C> DrawTarget for PackedBuffer<W, H, N, C>
 where
-    C: PixelColor,
-    <<C as PixelColor>::Raw as RawData>::Storage: Into<u8>,
+    C: PixelColor + IntoStorage<Storage = u8>,
 {
-    type Color = BinaryColor;
+    type Color = C;
     type Error = Infallible;
 
     fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
@@ -325,225 +476,560 @@ where
         Ok(())
     }
 
-    // fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
-    //     self.fill_rect(area, color);
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        // Axis-aligned lines are common in tables and grids, so use the single row/column paths
+        match area.size {
+            Size { height: 1, .. } => self.hline(area.top_left, area.size.width, color),
+            Size { width: 1, .. } => self.vline(area.top_left, area.size.height, color),
+            _ => self.fill_rect(area, color),
+        }
+
+        Ok(())
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let display = self.area;
 
-    //     Ok(())
-    // }
+        self.fill_rect_iter(area, &display, colors);
 
-    // fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
-    // where
-    //     I: IntoIterator<Item = Self::Color>,
-    // {
-    //     self.fill_rect_iter(area, colors);
+        Ok(())
+    }
 
-    //     Ok(())
-    // }
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        // The raster op is ignored like `DrawTarget::clear` does for other targets. Filling the
+        // display area rather than the whole buffer keeps the padding below the last row zeroed.
+        let area = self.area;
 
-    // fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
-    //     match color {
-    //         BinaryColor::Off => self.buf.fill(0x00),
-    //         BinaryColor::On => self.buf.fill(0xff),
-    //     }
+        self.fill_pattern(
+            &area,
+            mask::repeat(color.into_storage(), Self::SLOT_BITS),
+            RasterOp::Replace,
+        );
 
-    //     Ok(())
-    // }
+        Ok(())
+    }
 }
 
-// #[cfg(test)]
-#[cfg(feature = "DELETEME")]
+#[cfg(test)]
 mod tests {
     use super::*;
-    use embedded_graphics::{
-        primitives::{Circle, Primitive, PrimitiveStyle},
-        Drawable,
-    };
-    use embedded_graphics_core::{geometry::Point, pixelcolor::Rgb565, primitives::PointsIter};
-    use rand::{thread_rng, Rng};
+    use embedded_graphics::pixelcolor::{BinaryColor, Gray2, Gray4, Gray8, Rgb565, RgbColor};
+    use embedded_graphics_core::{geometry::Dimensions, primitives::PointsIter};
+    use proptest::prelude::*;
     use tinybmp::Bmp;
 
-    fn random_point() -> Point {
-        let mut rng = thread_rng();
-
-        let x: i32 = rng.gen_range(-256..256);
-        let y: i32 = rng.gen_range(-256..256);
-
-        Point::new(x, y)
+    /// Reference model of a display: a grid of colors, with `None` for pixels never drawn to.
+    struct Model<C> {
+        size: Size,
+        pixels: Vec<Option<C>>,
+        /// Corners of the area drawn to since the active area was last cleared.
+        active: Option<(Point, Point)>,
     }
 
-    #[test]
-    fn fuzz_fill() {
-        for i in 0..100_000 {
-            let mut disp_fill = PackedBuffer::<128, 64, 1024>::new();
-            let mut disp_pixels = PackedBuffer::<128, 64, 1024>::new();
+    impl<C: Copy> Model<C> {
+        fn new(size: Size) -> Self {
+            Self {
+                size,
+                pixels: vec![None; (size.width * size.height) as usize],
+                active: None,
+            }
+        }
 
-            let tl = random_point();
-            let br = random_point();
+        fn bounds(&self) -> Rectangle {
+            Rectangle::new(Point::zero(), self.size)
+        }
 
-            let area = Rectangle::with_corners(tl, br);
+        fn get(&self, point: Point) -> Option<C> {
+            self.pixels[(point.y as u32 * self.size.width + point.x as u32) as usize]
+        }
 
-            // Fill pixel by pixel
-            for point in area.points() {
-                disp_pixels.set_pixel(point, BinaryColor::On);
+        fn set(&mut self, point: Point, color: C) {
+            if !self.bounds().contains(point) {
+                return;
             }
 
-            disp_fill.fill_solid(&area, BinaryColor::On).ok();
+            self.pixels[(point.y as u32 * self.size.width + point.x as u32) as usize] = Some(color);
 
-            assert_eq!(disp_fill, disp_pixels, "{i}: {:?}", area);
+            self.active = Some(match self.active {
+                Some((tl, br)) => (tl.component_min(point), br.component_max(point)),
+                None => (point, point),
+            });
         }
-    }
 
-    #[test]
-    fn contiguous_zero() {
-        let mut disp_fill = PackedBuffer::<128, 64, 1024>::new();
-        let mut disp_pixels = PackedBuffer::<128, 64, 1024>::new();
+        fn fill(&mut self, area: &Rectangle, color: C) {
+            area.points().for_each(|point| self.set(point, color));
+        }
 
-        let tl = Point::zero();
+        fn contiguous(&mut self, area: &Rectangle, colors: impl Iterator<Item = C>) {
+            area.points()
+                .zip(colors)
+                .for_each(|(point, color)| self.set(point, color));
+        }
 
-        let bmp: Bmp<Rgb565> = Bmp::from_slice(include_bytes!("../benches/dvd.bmp"))
-            .expect("Failed to load BMP image");
+        fn clear(&mut self, color: C) {
+            self.fill(&self.bounds(), color);
+        }
 
-        let pixels = bmp.pixels().map(|p| (p.0, p.1.into()));
+        fn active_area(&self) -> Rectangle {
+            self.active
+                .map(|(tl, br)| Rectangle::with_corners(tl, br))
+                .unwrap_or_else(Rectangle::zero)
+        }
 
-        let area = Rectangle::new(tl, bmp.size());
+        fn clear_active_area(&mut self) {
+            self.active = None;
+        }
+    }
 
-        // Fill pixel by pixel
-        for (point, color) in pixels.clone() {
-            disp_pixels.set_pixel(point + area.top_left, color);
+    /// Check a display has the same contents and active area as the model. Pixels that were never
+    /// drawn should still have the initial zero value.
+    fn check_model<const W: u32, const H: u32, const N: usize, C>(
+        display: &PackedBuffer<W, H, N, C>,
+        model: &Model<C>,
+    ) -> Result<(), TestCaseError>
+    where
+        C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw> + core::fmt::Debug,
+    {
+        for point in model.bounds().points() {
+            prop_assert_eq!(
+                display.get_pixel(point),
+                Some(model.get(point).unwrap_or(pixels::from_storage(0))),
+                "{:?}",
+                point
+            );
         }
 
-        disp_fill.fill_contiguous(&area, pixels.map(|p| p.1)).ok();
+        prop_assert_eq!(display.active_area(), model.active_area());
 
-        assert_eq!(disp_fill, disp_pixels, "{:?}", area);
+        Ok(())
     }
 
-    #[test]
-    fn fuzz_contiguous() {
-        for i in 0..10_000 {
-            let mut disp_fill = PackedBuffer::<128, 64, 1024>::new();
-            let mut disp_pixels = PackedBuffer::<128, 64, 1024>::new();
+    #[derive(Debug, Clone)]
+    enum Op {
+        Fill(Rectangle, u8),
+        /// Fill an area with a pattern of colors generated from the seed.
+        Contiguous(Rectangle, u8),
+        Clear(u8),
+        /// Copy an area of a patterned source buffer to the given point.
+        Blit(Rectangle, Point),
+        /// Fill an area of a sub target, given in the sub target's coordinates.
+        SubFill(Rectangle, Rectangle, u8),
+        /// Fill an area of a sub target with a pattern of colors generated from the seed.
+        SubContiguous(Rectangle, Rectangle, u8),
+        ClearActiveArea,
+    }
 
-            let tl = {
-                let mut rng = thread_rng();
+    /// Rectangles anywhere from fully off screen on one side to fully off screen on the other,
+    /// including zero sized ones.
+    fn rect(width: u32, height: u32) -> impl Strategy<Value = Rectangle> {
+        let (w, h) = (width as i32, height as i32);
 
-                let x: i32 = rng.gen_range(-60..130);
-                let y: i32 = rng.gen_range(-30..70);
+        (-w * 2..w * 2, -h * 2..h * 2, 0..width * 3, 0..height * 3)
+            .prop_map(|(x, y, w, h)| Rectangle::new(Point::new(x, y), Size::new(w, h)))
+    }
 
-                Point::new(x, y)
-            };
+    fn op(width: u32, height: u32) -> impl Strategy<Value = Op> {
+        let (w, h) = (width as i32, height as i32);
+
+        prop_oneof![
+            4 => (rect(width, height), any::<u8>()).prop_map(|(r, c)| Op::Fill(r, c)),
+            2 => (rect(width, height), any::<u8>()).prop_map(|(r, s)| Op::Contiguous(r, s)),
+            1 => any::<u8>().prop_map(Op::Clear),
+            2 => (rect(width, height), -w..w * 2, -h..h * 2)
+                .prop_map(|(r, x, y)| Op::Blit(r, Point::new(x, y))),
+            2 => (rect(width, height), rect(width, height), any::<u8>())
+                .prop_map(|(s, r, c)| Op::SubFill(s, r, c)),
+            2 => (rect(width, height), rect(width, height), any::<u8>())
+                .prop_map(|(s, r, c)| Op::SubContiguous(s, r, c)),
+            1 => Just(Op::ClearActiveArea),
+        ]
+    }
 
-            let bmp: Bmp<Rgb565> = Bmp::from_slice(include_bytes!("../benches/dvd.bmp"))
-                .expect("Failed to load BMP image");
+    /// Convert a random value into a valid color.
+    fn color<C>(value: u8) -> C
+    where
+        C: PixelColor + From<C::Raw>,
+    {
+        pixels::from_storage(value & mask::slot_mask(C::Raw::BITS_PER_PIXEL as u32))
+    }
 
-            let pixels = bmp.pixels().map(|p| (p.0, p.1.into()));
+    /// A repeatable pattern of colors.
+    fn pattern<C>(seed: u8) -> impl Iterator<Item = C>
+    where
+        C: PixelColor + From<C::Raw>,
+    {
+        (0u32..).map(move |i| color((i * 7 + i / 5 + u32::from(seed)) as u8))
+    }
+
+    /// Apply each operation to both a display and the reference model, then compare them.
+    fn run_model<const W: u32, const H: u32, const N: usize, C>(
+        ops: &[Op],
+    ) -> Result<(), TestCaseError>
+    where
+        C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw> + core::fmt::Debug,
+    {
+        let mut source = PackedBuffer::<W, H, N, C>::new();
+        source
+            .fill_contiguous(&source.bounding_box(), pattern(3))
+            .unwrap();
+
+        let mut display = PackedBuffer::<W, H, N, C>::new();
+        let mut model = Model::new(display.size());
+
+        for op in ops {
+            match *op {
+                Op::Fill(area, value) => {
+                    display.fill_solid(&area, color(value)).unwrap();
+                    model.fill(&area, color(value));
+                }
+                Op::Contiguous(area, seed) => {
+                    display.fill_contiguous(&area, pattern(seed)).unwrap();
+                    model.contiguous(&area, pattern(seed));
+                }
+                Op::Clear(value) => {
+                    display.clear(color(value)).unwrap();
+                    model.clear(color(value));
+                }
+                Op::Blit(area, dest) => {
+                    display.blit(source.as_slice(), &area, dest);
+
+                    for point in area.points() {
+                        if let Some(color) = source.get_pixel(point) {
+                            model.set(dest + (point - area.top_left), color);
+                        }
+                    }
+                }
+                Op::SubFill(sub, area, value) => {
+                    display
+                        .sub_target(&sub)
+                        .fill_solid(&area, color(value))
+                        .unwrap();
+
+                    let area = Rectangle::new(area.top_left + sub.top_left, area.size);
+                    model.fill(&area.intersection(&sub), color(value));
+                }
+                Op::SubContiguous(sub, area, seed) => {
+                    display
+                        .sub_target(&sub)
+                        .fill_contiguous(&area, pattern(seed))
+                        .unwrap();
+
+                    let area = Rectangle::new(area.top_left + sub.top_left, area.size);
+
+                    for (point, color) in area.points().zip(pattern(seed)) {
+                        if sub.contains(point) {
+                            model.set(point, color);
+                        }
+                    }
+                }
+                Op::ClearActiveArea => {
+                    display.clear_active_area();
+                    model.clear_active_area();
+                }
+            }
+        }
 
-            let area = Rectangle::new(tl, bmp.size());
+        check_model(&display, &model)
+    }
 
-            // Fill pixel by pixel
-            for (point, color) in pixels.clone() {
-                disp_pixels.set_pixel(point + area.top_left, color);
+    macro_rules! model_tests {
+        ($($name:ident: $width:literal x $height:literal, $color:ty;)*) => {
+            proptest! {
+                $(
+                    #[test]
+                    fn $name(ops in prop::collection::vec(op($width, $height), 1..8)) {
+                        run_model::<$width, $height, { buffer_size::<$color>($width, $height) }, $color>(
+                            &ops,
+                        )?;
+                    }
+                )*
             }
+        };
+    }
 
-            disp_fill.fill_contiguous(&area, pixels.map(|p| p.1)).ok();
+    model_tests! {
+        model_binary_multiple_of_8: 16 x 24, BinaryColor;
+        model_binary_width_not_multiple_of_8: 13 x 16, BinaryColor;
+        model_binary_height_not_multiple_of_8: 16 x 13, BinaryColor;
+        model_binary_neither_multiple_of_8: 13 x 11, BinaryColor;
+        model_gray2: 13 x 11, Gray2;
+        model_gray4: 16 x 13, Gray4;
+        model_gray8: 13 x 5, Gray8;
+    }
 
-            assert_eq!(disp_fill, disp_pixels, "{i}: {:?}", area);
+    /// The cases listed in the README, for one display size.
+    fn readme_matrix<const W: u32, const H: u32, const N: usize>() {
+        let (w, h) = (W as i32, H as i32);
+        let center = Point::new(w / 2, h / 2);
+        let size = Size::new(W, H);
+
+        let cases = [
+            ("perfect fill", vec![Rectangle::new(Point::zero(), size)]),
+            ("zero sized at origin", vec![Rectangle::zero()]),
+            (
+                "zero sized at center",
+                vec![Rectangle::new(center, Size::zero())],
+            ),
+            (
+                "larger than display on all sides",
+                vec![Rectangle::new(Point::new(-3, -5), size + Size::new(9, 11))],
+            ),
+            (
+                "half off each edge",
+                vec![
+                    Rectangle::with_center(Point::new(0, h / 2), size / 2),
+                    Rectangle::with_center(Point::new(w - 1, h / 2), size / 2),
+                    Rectangle::with_center(Point::new(w / 2, 0), size / 2),
+                    Rectangle::with_center(Point::new(w / 2, h - 1), size / 2),
+                ],
+            ),
+            (
+                "half off each corner",
+                vec![
+                    Rectangle::with_center(Point::zero(), size / 2),
+                    Rectangle::with_center(Point::new(w - 1, 0), size / 2),
+                    Rectangle::with_center(Point::new(0, h - 1), size / 2),
+                    Rectangle::with_center(Point::new(w - 1, h - 1), size / 2),
+                ],
+            ),
+            (
+                "two overlapping rectangles",
+                vec![
+                    Rectangle::new(Point::new(1, 2), size / 2),
+                    Rectangle::with_center(center, size / 2),
+                ],
+            ),
+            (
+                "single pixel high",
+                vec![Rectangle::new(Point::new(2, 3), Size::new(W - 3, 1))],
+            ),
+            (
+                "single pixel wide",
+                vec![Rectangle::new(Point::new(3, 2), Size::new(1, H - 3))],
+            ),
+        ];
+
+        let background = Rectangle::new(Point::new(1, 1), size - Size::new(2, 2));
+
+        for (name, rects) in cases {
+            for with_background in [false, true] {
+                for color in [BinaryColor::On, BinaryColor::Off] {
+                    for contiguous in [false, true] {
+                        let mut display = PackedBuffer::<W, H, N, BinaryColor>::new();
+                        let mut model = Model::new(size);
+
+                        if with_background {
+                            display.fill_solid(&background, BinaryColor::On).unwrap();
+                            model.fill(&background, BinaryColor::On);
+                        }
+
+                        for rect in &rects {
+                            if contiguous {
+                                let colors = core::iter::repeat(color);
+
+                                display.fill_contiguous(rect, colors.clone()).unwrap();
+                                model.contiguous(rect, colors);
+                            } else {
+                                display.fill_solid(rect, color).unwrap();
+                                model.fill(rect, color);
+                            }
+                        }
+
+                        if let Err(e) = check_model(&display, &model) {
+                            panic!(
+                                "{} ({}x{}, background: {}, {:?}, contiguous: {}): {}",
+                                name, W, H, with_background, color, contiguous, e
+                            );
+                        }
+                    }
+                }
+            }
         }
     }
 
     #[test]
-    fn active_area_fuzz_contiguous() {
-        for _ in 0..10_000 {
-            let mut disp_fill = PackedBuffer::<128, 64, 1024>::new();
+    fn readme_cases() {
+        readme_matrix::<16, 24, { buffer_size::<BinaryColor>(16, 24) }>();
+        readme_matrix::<13, 16, { buffer_size::<BinaryColor>(13, 16) }>();
+        readme_matrix::<16, 13, { buffer_size::<BinaryColor>(16, 13) }>();
+        readme_matrix::<13, 11, { buffer_size::<BinaryColor>(13, 11) }>();
+    }
+
+    #[test]
+    fn block_offset() {
+        let mut disp = PackedBuffer::<132, 64, { 132 * 64 / 8 }, BinaryColor>::new();
+
+        disp.set_pixel(Point::new(0, 0), BinaryColor::On);
+        disp.set_pixel(Point::new(0, 10), BinaryColor::On);
+        disp.set_pixel(Point::new(0, 16), BinaryColor::On);
+        disp.set_pixel(Point::new(0, 20), BinaryColor::On);
 
-            let tl = {
-                let mut rng = thread_rng();
+        let mut blocks = disp.active_blocks();
 
-                let x: i32 = rng.gen_range(-60..130);
-                let y: i32 = rng.gen_range(-30..70);
+        let block1 = blocks.next().unwrap();
+        let block2 = blocks.next().unwrap();
+        let block3 = blocks.next().unwrap();
 
-                Point::new(x, y)
-            };
+        assert_eq!(blocks.next(), None);
 
-            let bmp: Bmp<Rgb565> = Bmp::from_slice(include_bytes!("../benches/dvd.bmp"))
-                .expect("Failed to load BMP image");
+        assert_eq!(block1[0], 0b0000_0001);
+        assert_eq!(block2[0], 0b0000_0100);
+        assert_eq!(block3[0], 0b0001_0001);
+    }
 
-            let pixels = bmp.pixels().map(|p| (p.0, p.1.into()));
+    fn dvd() -> Bmp<'static, Rgb565> {
+        Bmp::from_slice(include_bytes!("../benches/dvd.bmp")).expect("Failed to load BMP image")
+    }
+
+    /// Draw the image with `fill_contiguous` and pixel by pixel, and check the results match.
+    fn check_image<const W: u32, const H: u32, const N: usize, C>(
+        top_left: Point,
+        op: RasterOp,
+        color: impl Fn(Rgb565) -> C,
+    ) where
+        C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw> + core::fmt::Debug,
+    {
+        let bmp = dvd();
+        let area = Rectangle::new(top_left, bmp.size());
 
-            let area = Rectangle::new(tl, bmp.size());
+        let mut disp_fill = PackedBuffer::<W, H, N, C>::new();
+        let mut disp_pixels = PackedBuffer::<W, H, N, C>::new();
 
-            let visible_image = Rectangle::new(Point::zero(), disp_fill.size()).intersection(&area);
+        // Background to check bits outside the image are preserved
+        let background = Rectangle::new(Point::new(3, 5), Size::new(W - 6, H - 10));
+        disp_fill.fill_rect(&background, color(Rgb565::WHITE));
+        disp_pixels.fill_rect(&background, color(Rgb565::WHITE));
+        disp_fill.clear_active_area();
 
-            disp_fill.fill_contiguous(&area, pixels.map(|p| p.1)).ok();
+        disp_fill.set_raster_op(op);
+        disp_pixels.set_raster_op(op);
 
-            assert_eq!(disp_fill.active_area.rectangle(), visible_image);
+        for Pixel(point, c) in bmp.pixels() {
+            disp_pixels.set_pixel(point + top_left, color(c));
         }
+
+        disp_fill
+            .fill_contiguous(&area, bmp.pixels().map(|Pixel(_, c)| color(c)))
+            .unwrap();
+
+        let diff = disp_fill.diff(&disp_pixels);
+        assert!(diff.is_empty(), "{:?} {:?}\n{}", area, op, diff);
+        assert_eq!(
+            disp_fill.active_area(),
+            area.intersection(&disp_fill.bounding_box())
+        );
     }
 
     #[test]
-    fn bmp() {
-        let mut disp_fill = PackedBuffer::<32, 16, { 32 * 16 / 8 }>::new();
-        let mut disp_pixels = PackedBuffer::<32, 16, { 32 * 16 / 8 }>::new();
+    fn clear() {
+        let mut display = PackedBuffer::<16, 13, { buffer_size::<Gray4>(16, 13) }, Gray4>::new();
 
-        let tl = Point::new(-5, -5);
+        display.set_raster_op(RasterOp::Xor);
+        display.clear(Gray4::new(0b1001)).unwrap();
 
-        let bmp: Bmp<Rgb565> = Bmp::from_slice(include_bytes!("../benches/dvd.bmp"))
-            .expect("Failed to load BMP image");
+        assert!(display
+            .pixels()
+            .all(|Pixel(_, color)| color == Gray4::new(0b1001)));
+        assert_eq!(display.active_area(), display.bounding_box());
 
-        let pixels = bmp.pixels().map(|p| (p.0, p.1.into()));
+        // Padding below the last row is left zeroed, so the raw bytes match per-pixel drawing
+        let mut expected = PackedBuffer::<16, 13, { buffer_size::<Gray4>(16, 13) }, Gray4>::new();
+        for point in expected.bounding_box().points() {
+            expected.set_pixel(point, Gray4::new(0b1001));
+        }
 
-        let area = Rectangle::new(tl, bmp.size());
+        assert_eq!(display.buf, expected.buf);
 
-        // Fill pixel by pixel
-        for (point, color) in pixels.clone() {
-            disp_pixels.set_pixel(point + area.top_left, color);
-        }
+        let mut display = PackedBuffer::<8, 13, 16, BinaryColor>::new();
+        display.clear(BinaryColor::On).unwrap();
 
-        disp_fill.fill_contiguous(&area, pixels.map(|p| p.1)).ok();
+        assert_eq!(display.buf[8..], [0b1_1111; 8]);
+    }
 
-        assert_eq!(disp_fill, disp_pixels, "{:?}", area);
+    #[test]
+    fn contiguous_matches_set_pixel() {
+        for y in -30..70 {
+            for x in (-60..130).step_by(17) {
+                check_image::<128, 64, 1024, BinaryColor>(
+                    Point::new(x, y),
+                    RasterOp::Replace,
+                    Into::into,
+                );
+            }
+        }
     }
 
     #[test]
-    fn active_area_blocks() {
-        let mut disp_fill = PackedBuffer::<128, 64, { 128 * 64 / 8 }>::new();
+    fn contiguous_gray2() {
+        let gray = |c: Rgb565| {
+            let luma = (u16::from(c.r()) + u16::from(c.g()) / 2 + u16::from(c.b())) / 24;
 
-        let tl = Point::new(2, 2);
+            Gray2::new(luma as u8)
+        };
+
+        for y in -30..40 {
+            check_image::<40, 30, { buffer_size::<Gray2>(40, 30) }, Gray2>(
+                Point::new(y - 10, y),
+                RasterOp::Replace,
+                gray,
+            );
+        }
+    }
 
-        let bmp: Bmp<Rgb565> = Bmp::from_slice(include_bytes!("../benches/dvd.bmp"))
-            .expect("Failed to load BMP image");
+    #[test]
+    fn contiguous_raster_op() {
+        for op in [RasterOp::Or, RasterOp::Xor, RasterOp::AndNot] {
+            for tl in [Point::new(0, 0), Point::new(-7, 11), Point::new(100, 50)] {
+                check_image::<128, 64, 1024, BinaryColor>(tl, op, Into::into);
+            }
+        }
+    }
 
-        let pixels = bmp.pixels().map(|p| (p.0, p.1.into()));
+    #[test]
+    fn contiguous_active_blocks() {
+        let mut display = PackedBuffer::<128, 64, { 128 * 64 / 8 }, BinaryColor>::new();
 
-        let area = Rectangle::new(tl, bmp.size());
+        let bmp = dvd();
+        let area = Rectangle::new(Point::new(2, 2), bmp.size());
 
-        disp_fill.fill_contiguous(&area, pixels.map(|p| p.1)).ok();
+        display
+            .fill_contiguous(&area, bmp.pixels().map(|Pixel(_, c)| c.into()))
+            .unwrap();
 
-        assert_eq!(disp_fill.active_blocks().count(), 4);
+        assert_eq!(
+            display.active_blocks().count(),
+            (area.size.height as usize + 2).div_ceil(8)
+        );
 
-        for block in disp_fill.active_blocks() {
+        for block in display.active_blocks() {
             assert_eq!(block.len(), area.size.width as usize);
         }
     }
 
     #[test]
-    fn block_offset() {
-        let mut disp = PackedBuffer::<132, 64, { 132 * 64 / 8 }>::new();
+    fn contiguous_short_iterator() {
+        let mut display = PackedBuffer::<16, 16, 32, BinaryColor>::new();
+        display.clear(BinaryColor::On).unwrap();
 
-        disp.set_pixel(Point::new(0, 0), BinaryColor::On);
-        disp.set_pixel(Point::new(0, 10), BinaryColor::On);
-        disp.set_pixel(Point::new(0, 16), BinaryColor::On);
-        disp.set_pixel(Point::new(0, 20), BinaryColor::On);
+        let area = Rectangle::new(Point::new(0, 0), Size::new(4, 4));
 
-        let mut blocks = disp.active_blocks();
+        // Missing pixels are left untouched
+        display
+            .fill_contiguous(&area, core::iter::repeat_n(BinaryColor::Off, 6))
+            .unwrap();
 
-        let block1 = blocks.next().unwrap();
-        let block2 = blocks.next().unwrap();
-        let block3 = blocks.next().unwrap();
+        assert_eq!(&display.buf[0..5], &[!0b11, !0b11, !0b01, !0b01, 0xff]);
 
-        assert_eq!(blocks.next(), None);
+        // Also when the area starts part way through a page
+        let area = Rectangle::new(Point::new(8, 3), Size::new(2, 4));
 
-        assert_eq!(block1[0], 0b0000_0001);
-        assert_eq!(block2[0], 0b0000_0100);
-        assert_eq!(block3[0], 0b0001_0001);
+        display
+            .fill_contiguous(&area, core::iter::repeat_n(BinaryColor::Off, 2))
+            .unwrap();
+
+        assert_eq!(&display.buf[7..11], &[0xff, !0b1000, !0b1000, 0xff]);
     }
 }
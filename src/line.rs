@@ -0,0 +1,200 @@
+use crate::{
+    byte_direction::{ByteDirection, VerticalByte},
+    mask::{self, slot_mask},
+    merge::merge,
+    PackedBuffer,
+};
+use embedded_graphics_core::{
+    geometry::{Point, Size},
+    pixelcolor::{IntoStorage, PixelColor},
+    primitives::Rectangle,
+};
+
+impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    /// Draw a horizontal line `width` pixels long, starting at `start` and extending to the right.
+    ///
+    /// The line is clipped to the display dimensions. A horizontal line lies within a single
+    /// block, so this merges the same bits into each byte of one block row. The current raster
+    /// operation is applied.
+    pub fn hline(&mut self, start: Point, width: u32, color: C) {
+        let line = Rectangle::new(start, Size::new(width, 1)).intersection(&self.area);
+
+        if line.is_zero_sized() {
+            return;
+        }
+
+        self.active_area.update_from_rect(line);
+
+        let (start_idx, shift) = VerticalByte::<W>::locate(line.top_left, Self::SLOT_BITS);
+
+        let mask = slot_mask(Self::SLOT_BITS) << shift;
+        let color = color.into_storage() << shift;
+
+        merge(
+            &mut self.buf[start_idx..start_idx + line.size.width as usize],
+            color,
+            mask,
+            self.op,
+        );
+    }
+
+    /// Draw a vertical line `height` pixels long, starting at `start` and extending downwards.
+    ///
+    /// The line is clipped to the display dimensions. A vertical line touches a single byte in
+    /// each page it crosses, so each byte is merged with the part of the line inside that page. The
+    /// current raster operation is applied.
+    pub fn vline(&mut self, start: Point, height: u32, color: C) {
+        // Clip the single column directly, which is cheaper than a general rectangle intersection
+        if !(0..W as i32).contains(&start.x) {
+            return;
+        }
+
+        let top = start.y.max(0);
+        let bottom = (i64::from(start.y) + i64::from(height)).min(i64::from(H)) as i32;
+
+        if top >= bottom {
+            return;
+        }
+
+        let line = Rectangle::new(
+            Point::new(start.x, top),
+            Size::new(1, (bottom - top) as u32),
+        );
+
+        self.active_area.update_from_rect(line);
+
+        let pattern = mask::repeat(color.into_storage(), Self::SLOT_BITS);
+        let op = self.op;
+
+        // First and last bits of the line in the column
+        let bit_start = top as u32 * Self::SLOT_BITS;
+        let bit_end = bottom as u32 * Self::SLOT_BITS - 1;
+
+        let first_page = (bit_start / u8::BITS) as usize;
+        let last_page = (bit_end / u8::BITS) as usize;
+
+        let top_mask = u8::MAX << (bit_start % u8::BITS);
+        let bottom_mask = u8::MAX >> (u8::BITS - 1 - bit_end % u8::BITS);
+
+        let column = self.buf[first_page * W as usize + start.x as usize..]
+            .iter_mut()
+            .step_by(W as usize)
+            .take(last_page - first_page + 1);
+
+        for (page, byte) in (first_page..).zip(column) {
+            let mut mask = u8::MAX;
+
+            if page == first_page {
+                mask &= top_mask;
+            }
+
+            if page == last_page {
+                mask &= bottom_mask;
+            }
+
+            *byte = op.apply(*byte, pattern, mask);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{buffer_size, PackedBuffer, RasterOp};
+    use embedded_graphics::pixelcolor::{BinaryColor, Gray2};
+    use embedded_graphics_core::{
+        draw_target::DrawTarget,
+        geometry::{Point, Size},
+        primitives::{PointsIter, Rectangle},
+    };
+
+    type Display = PackedBuffer<20, 30, { buffer_size::<Gray2>(20, 30) }, Gray2>;
+
+    const OPS: [RasterOp; 5] = [
+        RasterOp::Replace,
+        RasterOp::Or,
+        RasterOp::And,
+        RasterOp::Xor,
+        RasterOp::AndNot,
+    ];
+
+    fn background() -> Display {
+        let mut display = Display::new();
+
+        display.fill_rect(
+            &Rectangle::new(Point::new(2, 3), Size::new(14, 20)),
+            Gray2::new(0b10),
+        );
+        display.clear_active_area();
+
+        display
+    }
+
+    #[test]
+    fn lines_match_set_pixel() {
+        let starts = [
+            Point::new(0, 0),
+            Point::new(3, 5),
+            Point::new(-4, 7),
+            Point::new(11, -6),
+            Point::new(19, 29),
+            Point::new(25, 4),
+        ];
+
+        for op in OPS {
+            for start in starts {
+                for length in [0, 1, 7, 9, 40] {
+                    let lines = [
+                        (Rectangle::new(start, Size::new(length, 1)), true),
+                        (Rectangle::new(start, Size::new(1, length)), false),
+                    ];
+
+                    for (line, horizontal) in lines {
+                        let mut fast = background();
+                        let mut pixels = background();
+
+                        fast.set_raster_op(op);
+                        pixels.set_raster_op(op);
+
+                        if horizontal {
+                            fast.hline(start, length, Gray2::new(0b11));
+                        } else {
+                            fast.vline(start, length, Gray2::new(0b11));
+                        }
+
+                        for point in line.points() {
+                            pixels.set_pixel(point, Gray2::new(0b11));
+                        }
+
+                        assert_eq!(fast, pixels, "{:?} {:?}", op, line);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fill_solid_uses_lines() {
+        let areas = [
+            Rectangle::new(Point::new(1, 7), Size::new(13, 1)),
+            Rectangle::new(Point::new(6, -2), Size::new(1, 12)),
+            Rectangle::new(Point::new(-3, 4), Size::new(9, 17)),
+            Rectangle::new(Point::new(4, 4), Size::zero()),
+        ];
+
+        for area in areas {
+            let mut display = PackedBuffer::<16, 16, 32, BinaryColor>::new();
+            let mut pixels = PackedBuffer::<16, 16, 32, BinaryColor>::new();
+
+            display.fill_solid(&area, BinaryColor::On).unwrap();
+
+            for point in area.points() {
+                pixels.set_pixel(point, BinaryColor::On);
+            }
+
+            assert_eq!(display, pixels, "{:?}", area);
+        }
+    }
+}
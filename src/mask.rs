@@ -42,8 +42,8 @@ pub(crate) struct StartChunk {
 ///
 /// Given the area above that starts at y = 4 and ends at y = 15, the first 4 bits of the area
 /// inhabit part of an 8 bit block, denoted with `*`. `start_chunk` computes the bit mask for these
-/// first 4 bits as `0b00001111`, as well as returning the remaining number of rows in the block. In
-/// this case, 11.
+/// first 4 bits as `0b11110000`, as well as returning the remaining number of rows below the start
+/// chunk. In this case, 8.
 pub(crate) fn start_chunk(start: u32, end: u32) -> StartChunk {
     let len = end - start;
     let num_bits = u8::BITS;
@@ -79,3 +79,121 @@ pub(crate) fn start_chunk(start: u32, end: u32) -> StartChunk {
         remaining,
     }
 }
+
+/// Number of bits used to store a single pixel of the given bit depth.
+///
+/// Raw colors in embedded-graphics are always 1, 2, 4 or 8 bits wide, so each slot is exactly one
+/// pixel wide and pixels never straddle a byte boundary.
+pub(crate) const fn slot_bits(bpp: usize) -> u32 {
+    assert!(
+        matches!(bpp, 1 | 2 | 4 | 8),
+        "Only 1, 2, 4 and 8bpp pixels can be packed into bytes"
+    );
+
+    bpp as u32
+}
+
+/// Bit mask covering a single pixel slot in the least significant bits of a byte.
+pub(crate) const fn slot_mask(slot: u32) -> u8 {
+    u8::MAX >> (u8::BITS - slot)
+}
+
+/// Repeat a pixel value throughout every slot of a byte.
+pub(crate) const fn repeat(value: u8, slot: u32) -> u8 {
+    let mut byte = 0;
+    let mut shift = 0;
+
+    while shift < u8::BITS {
+        byte |= value << shift;
+        shift += slot;
+    }
+
+    byte
+}
+
+/// Create the bit mask for the part of a page covered by the bit range `start..end`.
+///
+/// `page_start` is the bit offset of the first bit in the page. Bits of the range outside the page
+/// are ignored.
+pub(crate) fn page_mask(page_start: u32, start: u32, end: u32) -> u8 {
+    let page_end = page_start + u8::BITS;
+
+    let start = start.clamp(page_start, page_end) - page_start;
+    let end = end.clamp(page_start, page_end) - page_start;
+
+    if start >= end {
+        return 0;
+    }
+
+    (u8::MAX >> (u8::BITS - (end - start))) << start
+}
+
+/// Expand the low bits of a 1bpp mask so that each bit covers a whole pixel slot.
+///
+/// Only the first `u8::BITS / slot` bits of `bits` are used.
+pub(crate) fn expand(bits: u8, slot: u32) -> u8 {
+    if slot == 1 {
+        return bits;
+    }
+
+    let mut expanded = 0;
+
+    for pixel in 0..u8::BITS / slot {
+        if bits & (1 << pixel) != 0 {
+            expanded |= slot_mask(slot) << (pixel * slot);
+        }
+    }
+
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots() {
+        assert_eq!(slot_bits(1), 1);
+        assert_eq!(slot_bits(2), 2);
+        assert_eq!(slot_bits(4), 4);
+        assert_eq!(slot_bits(8), 8);
+
+        assert_eq!(slot_mask(1), 0b0000_0001);
+        assert_eq!(slot_mask(4), 0b0000_1111);
+        assert_eq!(slot_mask(8), 0b1111_1111);
+    }
+
+    #[test]
+    fn repeat_color() {
+        assert_eq!(repeat(1, 1), 0xff);
+        assert_eq!(repeat(0, 1), 0x00);
+        assert_eq!(repeat(0b10, 2), 0b1010_1010);
+        assert_eq!(repeat(0b101, 4), 0b0101_0101);
+        assert_eq!(repeat(0x2a, 8), 0x2a);
+    }
+
+    #[test]
+    fn page_masks() {
+        assert_eq!(page_mask(0, 0, 8), 0xff);
+        assert_eq!(page_mask(0, 4, 16), 0b1111_0000);
+        assert_eq!(page_mask(8, 4, 11), 0b0000_0111);
+        assert_eq!(page_mask(8, 10, 12), 0b0000_1100);
+        assert_eq!(page_mask(16, 4, 11), 0);
+    }
+
+    #[test]
+    fn expand_mask() {
+        assert_eq!(expand(0b1010_0101, 1), 0b1010_0101);
+        assert_eq!(expand(0b1111_0101, 2), 0b0011_0011);
+        assert_eq!(expand(0b0000_0010, 4), 0b1111_0000);
+        assert_eq!(expand(0b0000_0001, 8), 0b1111_1111);
+    }
+
+    #[test]
+    fn start_chunk_unaligned() {
+        let StartChunk { mask, remaining } = start_chunk(4, 15);
+
+        assert_eq!(mask, 0b1111_0000);
+        assert_eq!(remaining, 8);
+    }
+}
@@ -0,0 +1,186 @@
+//! Masked merges of a repeated byte pattern into a slice of the buffer.
+//!
+//! These are the inner loops used when filling part of a block row. They're exported, but hidden
+//! from the docs, only so the byte and word-wide variants can be benchmarked against each other
+//! with `cargo bench --bench fill`.
+//!
+//! On targets with SIMD, the compiler already vectorises [`merge_bytes`] into wider stores than
+//! [`merge_words`] uses, so buffer fills use the byte variant there. Targets without SIMD, such as
+//! the Cortex-M0, use [`merge_words`] instead.
+
+use crate::RasterOp;
+
+/// Number of bytes in a word.
+///
+/// Words are `usize`s so they match the target's pointer width, which makes each word a single
+/// load and store, e.g. 4 bytes on 32-bit MCUs and 8 bytes on 64-bit hosts.
+const WORD: usize = core::mem::size_of::<usize>();
+
+/// Whether buffer fills use [`merge_words`] on this target.
+const USE_WORDS: bool = !cfg!(any(
+    target_feature = "sse2",
+    target_feature = "neon",
+    target_feature = "simd128"
+));
+
+/// Combine `pattern` into every byte of `dst` using `op`, changing only the bits set in `mask`.
+///
+/// This processes one byte at a time.
+#[inline]
+pub fn merge_bytes(dst: &mut [u8], pattern: u8, mask: u8, op: RasterOp) {
+    dst.iter_mut()
+        .for_each(|byte| *byte = op.apply(*byte, pattern, mask));
+}
+
+/// Combine `pattern` into every byte of `dst` using `op`, changing only the bits set in `mask`.
+///
+/// This gives the same result as [`merge_bytes`] but processes a whole machine word at a time.
+/// Bytes before the first word aligned address and after the last whole word are merged
+/// individually.
+#[inline]
+pub fn merge_words(dst: &mut [u8], pattern: u8, mask: u8, op: RasterOp) {
+    let head = dst.as_ptr().align_offset(WORD).min(dst.len());
+
+    let (head, body) = dst.split_at_mut(head);
+
+    merge_bytes(head, pattern, mask, op);
+
+    let pattern_word = usize::from_ne_bytes([pattern; WORD]);
+    let mask_word = usize::from_ne_bytes([mask; WORD]);
+
+    let mut words = body.chunks_exact_mut(WORD);
+
+    for word in words.by_ref() {
+        let value = usize::from_ne_bytes(word.try_into().unwrap());
+
+        word.copy_from_slice(&op.apply_word(value, pattern_word, mask_word).to_ne_bytes());
+    }
+
+    merge_bytes(words.into_remainder(), pattern, mask, op);
+}
+
+/// Combine `pattern` into every byte of `dst` using `op`, changing only the bits set in `mask`.
+///
+/// This is the merge used by buffer fills, picking whichever of [`merge_bytes`] and
+/// [`merge_words`] is faster on the target.
+#[inline]
+pub(crate) fn merge(dst: &mut [u8], pattern: u8, mask: u8, op: RasterOp) {
+    if use_words() {
+        merge_words(dst, pattern, mask, op)
+    } else {
+        merge_bytes(dst, pattern, mask, op)
+    }
+}
+
+#[cfg(not(test))]
+#[inline(always)]
+const fn use_words() -> bool {
+    USE_WORDS
+}
+
+#[cfg(test)]
+use tests::use_words;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    std::thread_local! {
+        /// Lets tests run buffer fills with either merge on any target.
+        static WORDS: Cell<bool> = const { Cell::new(USE_WORDS) };
+    }
+
+    pub(super) fn use_words() -> bool {
+        WORDS.with(Cell::get)
+    }
+
+    /// Run `f` with buffer fills using either [`merge_words`] or [`merge_bytes`].
+    fn with_merge<T>(words: bool, f: impl FnOnce() -> T) -> T {
+        let previous = WORDS.with(|cell| cell.replace(words));
+        let result = f();
+        WORDS.with(|cell| cell.set(previous));
+
+        result
+    }
+
+    #[test]
+    fn words_match_bytes() {
+        let ops = [
+            RasterOp::Replace,
+            RasterOp::Or,
+            RasterOp::And,
+            RasterOp::Xor,
+            RasterOp::AndNot,
+        ];
+
+        let original: [u8; 64] = core::array::from_fn(|i| (i as u8).wrapping_mul(37) ^ 0x5a);
+
+        for op in ops {
+            // Cover unaligned starts and lengths shorter than, equal to and longer than a word
+            for start in 0..WORD + 1 {
+                for end in start..original.len() {
+                    let mut bytes = original;
+                    let mut words = original;
+
+                    merge_bytes(&mut bytes[start..end], 0b1010_0110, 0b0011_1100, op);
+                    merge_words(&mut words[start..end], 0b1010_0110, 0b0011_1100, op);
+
+                    assert_eq!(bytes, words, "{:?} {}..{}", op, start, end);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn buffer_fills_match() {
+        use crate::{buffer_size, PackedBuffer};
+        use embedded_graphics::pixelcolor::Gray2;
+        use embedded_graphics_core::{draw_target::DrawTarget, prelude::*, primitives::Rectangle};
+
+        type Display = PackedBuffer<37, 29, { buffer_size::<Gray2>(37, 29) }, Gray2>;
+
+        let ops = [
+            RasterOp::Replace,
+            RasterOp::Or,
+            RasterOp::And,
+            RasterOp::Xor,
+            RasterOp::AndNot,
+        ];
+
+        // Odd widths and offsets so block rows start and end at different word alignments
+        let rects = [
+            Rectangle::new(Point::new(0, 0), Size::new(37, 29)),
+            Rectangle::new(Point::new(3, 1), Size::new(30, 2)),
+            Rectangle::new(Point::new(1, 5), Size::new(35, 17)),
+            Rectangle::new(Point::new(-4, 10), Size::new(50, 3)),
+            Rectangle::new(Point::new(9, 27), Size::new(11, 9)),
+        ];
+
+        let fill = |op: RasterOp| {
+            let mut display = Display::new();
+
+            for (i, point) in display.bounding_box().points().enumerate() {
+                display.set_pixel(point, Gray2::new((i * 7 % 4) as u8));
+            }
+
+            display.set_raster_op(op);
+
+            for (i, rect) in rects.iter().enumerate() {
+                display.fill_solid(rect, Gray2::new(i as u8 % 4)).unwrap();
+                display.hline(rect.top_left, rect.size.width, Gray2::new(3));
+            }
+
+            display
+        };
+
+        for op in ops {
+            let bytes = with_merge(false, || fill(op));
+            let words = with_merge(true, || fill(op));
+
+            let diff = words.diff(&bytes);
+            assert!(diff.is_empty(), "{:?}\n{}", op, diff);
+            assert_eq!(words.as_ref(), bytes.as_ref(), "{:?}", op);
+        }
+    }
+}
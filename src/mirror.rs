@@ -0,0 +1,597 @@
+//! A framed protocol for mirroring a buffer to a host over a byte stream.
+//!
+//! The device side encodes frames into any [`ByteSink`] without allocating, so it can write
+//! straight to a UART or USB serial port. With the `std` feature enabled, `Mirror` decodes the
+//! frames on the host and keeps a copy of the display contents, which can be drawn into any
+//! `DrawTarget` such as an `embedded-graphics-simulator` window.
+//!
+//! Every frame starts with a sync byte, a frame type and the payload length:
+//!
+//! | Offset | Size | Contents                              |
+//! |--------|------|---------------------------------------|
+//! | 0      | 1    | Sync byte, `0xa5`                     |
+//! | 1      | 1    | Frame type                            |
+//! | 2      | 4    | Payload length as a little endian u32 |
+//! | 6      |      | Payload                               |
+//!
+//! A header frame (type `0`) describes the display, and must be sent before any updates. Its
+//! payload is the width and height as little endian `u16`s, followed by the bits per pixel and the
+//! [`Layout`] (`0` for vertical).
+//!
+//! An update frame (type `1`) holds the dirty rectangle as little endian `u16` x, y, width and
+//! height, followed by the bytes covering it encoded as [`rle`](crate::rle) spans.
+//!
+//! Frames of unknown types are skipped by the decoder, so new frame types can be added without
+//! breaking older hosts.
+//!
+//! As sizes are sent as `u16`s, mirroring a buffer wider or taller than 65535 pixels fails to
+//! compile:
+//!
+//! ```rust,compile_fail
+//! use embedded_graphics_core::pixelcolor::BinaryColor;
+//! use packed_display_buffer::{PackedBuffer, SliceSink};
+//!
+//! let display = PackedBuffer::<65536, 1, 65536, BinaryColor>::new();
+//!
+//! let mut buf = [0u8; 16];
+//! display.write_mirror_header(&mut SliceSink::new(&mut buf)).unwrap();
+//! ```
+
+use crate::{byte_direction::Layout, pnm::ByteSink, PackedBuffer};
+use core::convert::Infallible;
+use embedded_graphics_core::{
+    pixelcolor::{raw::RawData, IntoStorage, PixelColor},
+    primitives::Rectangle,
+};
+
+const SYNC: u8 = 0xa5;
+
+const HEADER_FRAME: u8 = 0;
+const UPDATE_FRAME: u8 = 1;
+
+/// Length of the header frame payload.
+const HEADER_LEN: usize = 6;
+
+/// Counts bytes without storing them, to find frame lengths before writing.
+struct Counter(usize);
+
+impl ByteSink for Counter {
+    type Error = Infallible;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0 += bytes.len();
+
+        Ok(())
+    }
+}
+
+fn write_frame_header<S: ByteSink>(frame: u8, len: usize, sink: &mut S) -> Result<(), S::Error> {
+    sink.write_bytes(&[SYNC, frame])?;
+    sink.write_bytes(&(len as u32).to_le_bytes())
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    /// Write a header frame describing the buffer.
+    ///
+    /// This must be sent before any updates, and again if the host might have missed it, e.g.
+    /// after it reconnects. See the [`mirror`](crate::mirror) module for details of the protocol.
+    pub fn write_mirror_header<S: ByteSink>(&self, sink: &mut S) -> Result<(), S::Error> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::FITS_U16;
+
+        write_frame_header(HEADER_FRAME, HEADER_LEN, sink)?;
+
+        sink.write_bytes(&(W as u16).to_le_bytes())?;
+        sink.write_bytes(&(H as u16).to_le_bytes())?;
+        sink.write_bytes(&[C::Raw::BITS_PER_PIXEL as u8, Layout::Vertical as u8])
+    }
+
+    /// Write an update frame for the active area.
+    ///
+    /// Nothing is written if the active area is empty. The active area isn't cleared, so call
+    /// [`clear_active_area`](Self::clear_active_area) once the frame has been sent.
+    pub fn write_mirror_update<S: ByteSink>(&self, sink: &mut S) -> Result<(), S::Error> {
+        self.write_mirror_area(&self.active_area(), sink)
+    }
+
+    /// Write an update frame for the given area, e.g. the whole display to bring a newly
+    /// connected host up to date.
+    ///
+    /// The area is clipped to the display. Nothing is written if the clipped area is empty.
+    pub fn write_mirror_area<S: ByteSink>(
+        &self,
+        area: &Rectangle,
+        sink: &mut S,
+    ) -> Result<(), S::Error> {
+        let area = area.intersection(&self.area);
+
+        if area.is_zero_sized() {
+            return Ok(());
+        }
+
+        let mut len = Counter(8);
+        // Counting can't fail
+        let _ = self.write_rle_area(&area, &mut len);
+
+        write_frame_header(UPDATE_FRAME, len.0, sink)?;
+
+        let values = [
+            area.top_left.x as u32,
+            area.top_left.y as u32,
+            area.size.width,
+            area.size.height,
+        ];
+
+        for value in values {
+            sink.write_bytes(&(value as u16).to_le_bytes())?;
+        }
+
+        self.write_rle_area(&area, sink)
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+pub use decoder::*;
+
+#[cfg(any(test, feature = "std"))]
+mod decoder {
+    use super::*;
+    use crate::{mask::slot_bits, rle::apply_spans, RleError};
+    use core::fmt;
+    use embedded_graphics_core::{
+        draw_target::DrawTarget,
+        geometry::{Point, Size},
+        pixelcolor::Gray8,
+        primitives::PointsIter,
+        Pixel,
+    };
+    use std::io::{self, Read};
+
+    /// Error returned when mirror frames can't be decoded.
+    #[derive(Debug)]
+    pub enum MirrorError {
+        /// The stream couldn't be read.
+        Io(io::Error),
+
+        /// A frame is malformed, e.g. it doesn't start with the sync byte or is truncated.
+        InvalidFrame,
+
+        /// An update was received before the header describing the display.
+        MissingHeader,
+
+        /// The header describes a display with an unsupported color depth or layout.
+        UnsupportedFormat,
+
+        /// The update data couldn't be decoded.
+        Rle(RleError),
+    }
+
+    impl fmt::Display for MirrorError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                MirrorError::Io(e) => write!(f, "failed to read mirror stream: {}", e),
+                MirrorError::InvalidFrame => f.write_str("invalid mirror frame"),
+                MirrorError::MissingHeader => f.write_str("update received before header"),
+                MirrorError::UnsupportedFormat => f.write_str("unsupported display format"),
+                MirrorError::Rle(e) => write!(f, "invalid update data: {:?}", e),
+            }
+        }
+    }
+
+    impl std::error::Error for MirrorError {}
+
+    impl From<io::Error> for MirrorError {
+        fn from(e: io::Error) -> Self {
+            MirrorError::Io(e)
+        }
+    }
+
+    /// A frame received by a [`Mirror`].
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum Frame {
+        /// The display was described by a header frame, clearing the mirrored contents.
+        Header(Size),
+
+        /// The given area of the display was updated.
+        Update(Rectangle),
+    }
+
+    /// A host side copy of a display, kept up to date by decoding mirror frames.
+    ///
+    /// ```rust
+    /// use embedded_graphics_core::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle};
+    /// use packed_display_buffer::{mirror::Mirror, IoSink, PackedBuffer};
+    ///
+    /// let mut device = PackedBuffer::<128, 64, 1024, BinaryColor>::new();
+    /// device
+    ///     .fill_solid(&Rectangle::new(Point::new(10, 10), Size::new(50, 30)), BinaryColor::On)
+    ///     .unwrap();
+    ///
+    /// let mut stream = IoSink::new(Vec::new());
+    /// device.write_mirror_header(&mut stream).unwrap();
+    /// device.write_mirror_update(&mut stream).unwrap();
+    ///
+    /// let mut mirror = Mirror::new();
+    /// let data = stream.into_inner();
+    /// let mut reader = data.as_slice();
+    ///
+    /// while mirror.read_frame(&mut reader).unwrap().is_some() {}
+    ///
+    /// assert_eq!(mirror.get_raw(Point::new(10, 10)), Some(1));
+    /// ```
+    #[derive(Debug, Clone, Default)]
+    pub struct Mirror {
+        size: Size,
+        bpp: u8,
+        data: Vec<u8>,
+    }
+
+    impl Mirror {
+        /// Create an empty mirror, which will be sized by the first header frame.
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Read and apply the next frame from a stream.
+        ///
+        /// Returns `Ok(None)` if the stream ends cleanly between frames. An update with a span outside
+        /// the area it reports is rejected with [`MirrorError::Rle`], and any spans before it have
+        /// already been applied.
+        pub fn read_frame<R: Read>(
+            &mut self,
+            reader: &mut R,
+        ) -> Result<Option<Frame>, MirrorError> {
+            loop {
+                let mut header = [0u8; 6];
+
+                if reader.read(&mut header[..1])? == 0 {
+                    return Ok(None);
+                }
+
+                reader.read_exact(&mut header[1..]).map_err(truncated)?;
+
+                let [sync, frame, len @ ..] = header;
+
+                if sync != SYNC {
+                    return Err(MirrorError::InvalidFrame);
+                }
+
+                let len = u64::from(u32::from_le_bytes(len));
+
+                // Read through `take` so a corrupt length can't cause a huge allocation up front
+                let mut payload = Vec::new();
+                reader.take(len).read_to_end(&mut payload)?;
+
+                if payload.len() as u64 != len {
+                    return Err(MirrorError::InvalidFrame);
+                }
+
+                return match frame {
+                    HEADER_FRAME => self.header(&payload).map(Some),
+                    UPDATE_FRAME => self.update(&payload).map(Some),
+                    _ => continue,
+                };
+            }
+        }
+
+        fn header(&mut self, payload: &[u8]) -> Result<Frame, MirrorError> {
+            let payload: [u8; HEADER_LEN] =
+                payload.try_into().map_err(|_| MirrorError::InvalidFrame)?;
+
+            let [w0, w1, h0, h1, bpp, layout] = payload;
+
+            if !matches!(bpp, 1 | 2 | 4 | 8) || layout != Layout::Vertical as u8 {
+                return Err(MirrorError::UnsupportedFormat);
+            }
+
+            let size = Size::new(
+                u32::from(u16::from_le_bytes([w0, w1])),
+                u32::from(u16::from_le_bytes([h0, h1])),
+            );
+
+            self.size = size;
+            self.bpp = bpp;
+            self.data = vec![0; Layout::Vertical.len(size.width, size.height, self.slot())];
+
+            Ok(Frame::Header(size))
+        }
+
+        fn update(&mut self, payload: &[u8]) -> Result<Frame, MirrorError> {
+            if self.bpp == 0 {
+                return Err(MirrorError::MissingHeader);
+            }
+
+            let rect = payload.get(..8).ok_or(MirrorError::InvalidFrame)?;
+            let [x, y, width, height] = [0, 2, 4, 6]
+                .map(|offset| u32::from(u16::from_le_bytes([rect[offset], rect[offset + 1]])));
+
+            let area = Rectangle::new(Point::new(x as i32, y as i32), Size::new(width, height));
+
+            if area.intersection(&self.bounding_box()) != area {
+                return Err(MirrorError::InvalidFrame);
+            }
+
+            let slot = self.slot();
+
+            apply_spans(&mut self.data, self.size, slot, &area, &payload[8..], |_| {})
+                .map_err(MirrorError::Rle)?;
+
+            Ok(Frame::Update(area))
+        }
+
+        fn slot(&self) -> u32 {
+            slot_bits(usize::from(self.bpp))
+        }
+
+        /// The display size, or zero if no header has been received.
+        pub fn size(&self) -> Size {
+            self.size
+        }
+
+        /// Bits per pixel of the display, or zero if no header has been received.
+        pub fn bpp(&self) -> u8 {
+            self.bpp
+        }
+
+        fn bounding_box(&self) -> Rectangle {
+            Rectangle::new(Point::zero(), self.size)
+        }
+
+        /// Get the raw value of a pixel.
+        ///
+        /// Returns `None` if the point is outside the display.
+        pub fn get_raw(&self, point: Point) -> Option<u8> {
+            self.bounding_box().contains(point).then(|| {
+                let slot = self.slot();
+                let (idx, shift) = Layout::Vertical.locate(self.size.width, point, slot);
+
+                (self.data[idx] >> shift) & ((1 << self.bpp) - 1) as u8
+            })
+        }
+
+        /// Draw part of the mirrored display into a target, e.g. a `SimulatorDisplay`.
+        ///
+        /// Raw values are scaled to the full `Gray8` range, so 1bpp pixels that are on are drawn
+        /// as white, as they'd appear on an OLED panel.
+        pub fn draw_area<D>(&self, area: &Rectangle, target: &mut D) -> Result<(), D::Error>
+        where
+            D: DrawTarget<Color = Gray8>,
+        {
+            let max = (1u32 << self.bpp) - 1;
+            let area = area.intersection(&self.bounding_box());
+
+            target.draw_iter(area.points().filter_map(|point| {
+                let raw = u32::from(self.get_raw(point)?);
+
+                Some(Pixel(point, Gray8::new((raw * 255 / max) as u8)))
+            }))
+        }
+
+        /// Draw the whole mirrored display into a target.
+        ///
+        /// See [`draw_area`](Self::draw_area) for details.
+        pub fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+        where
+            D: DrawTarget<Color = Gray8>,
+        {
+            self.draw_area(&self.bounding_box(), target)
+        }
+    }
+
+    /// Report a stream that ends part way through a frame as an invalid frame.
+    fn truncated(e: io::Error) -> MirrorError {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            MirrorError::InvalidFrame
+        } else {
+            MirrorError::Io(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer_size, SliceSink};
+    use embedded_graphics::{pixelcolor::Gray2, prelude::*};
+
+    type Display = PackedBuffer<20, 10, { buffer_size::<Gray2>(20, 10) }, Gray2>;
+
+    #[test]
+    fn frames() {
+        let mut display = Display::new();
+
+        let mut buf = [0u8; 256];
+        let mut sink = SliceSink::new(&mut buf);
+
+        display.write_mirror_header(&mut sink).unwrap();
+        assert_eq!(sink.written(), [0xa5, 0, 6, 0, 0, 0, 20, 0, 10, 0, 2, 0]);
+
+        // Nothing to update
+        display.write_mirror_update(&mut sink).unwrap();
+        assert_eq!(sink.written().len(), 12);
+
+        display.set_pixel(Point::new(3, 5), Gray2::new(3));
+
+        let mut sink = SliceSink::new(&mut buf);
+        display.write_mirror_update(&mut sink).unwrap();
+
+        assert_eq!(
+            sink.written(),
+            [
+                0xa5,
+                1,
+                16,
+                0,
+                0,
+                0, // Frame header
+                3,
+                0,
+                5,
+                0,
+                1,
+                0,
+                1,
+                0, // Dirty rectangle
+                1,
+                0,
+                3,
+                0,
+                1,
+                0,
+                0,
+                0b0000_1100, // Span for page 1
+            ]
+        );
+    }
+
+    #[test]
+    fn pipe() {
+        use crate::IoSink;
+        use embedded_graphics::{
+            pixelcolor::Gray8,
+            primitives::{Circle, PrimitiveStyle},
+        };
+        use std::{io::pipe, thread};
+
+        let (mut reader, writer) = pipe().unwrap();
+
+        let device = thread::spawn(move || {
+            let mut display = Display::new();
+            let mut sink = IoSink::new(writer);
+
+            display.write_mirror_header(&mut sink).unwrap();
+
+            for (i, color) in [1, 2, 3, 0, 2].into_iter().enumerate() {
+                Circle::new(Point::new(i as i32 * 3 - 2, 1), 9)
+                    .into_styled(PrimitiveStyle::with_fill(Gray2::new(color)))
+                    .draw(&mut display)
+                    .unwrap();
+
+                display.write_mirror_update(&mut sink).unwrap();
+                display.clear_active_area();
+            }
+
+            // Garbage frames from a newer device are ignored
+            sink.write_bytes(&[SYNC, 0x7f, 2, 0, 0, 0, 1, 2]).unwrap();
+
+            display
+        });
+
+        let mut mirror = Mirror::new();
+        let mut frames = Vec::new();
+
+        while let Some(frame) = mirror.read_frame(&mut reader).unwrap() {
+            frames.push(frame);
+        }
+
+        let display = device.join().unwrap();
+
+        assert_eq!(frames.len(), 6);
+        assert_eq!(frames[0], Frame::Header(Size::new(20, 10)));
+        assert_eq!(
+            frames[1],
+            Frame::Update(
+                Rectangle::new(Point::new(-2, 1), Size::new(9, 9))
+                    .intersection(&display.bounding_box())
+            )
+        );
+
+        for point in display.bounding_box().points() {
+            assert_eq!(
+                mirror.get_raw(point),
+                display.get_pixel(point).map(IntoStorage::into_storage),
+                "{:?}",
+                point
+            );
+        }
+
+        // Draw into another buffer to check the conversion to `Gray8`
+        let mut target = PackedBuffer::<20, 10, { buffer_size::<Gray8>(20, 10) }, Gray8>::new();
+        mirror.draw(&mut target).unwrap();
+
+        for Pixel(point, color) in display.pixels() {
+            assert_eq!(
+                target.get_pixel(point),
+                Some(Gray8::new(color.luma() * 0x55))
+            );
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let mut mirror = Mirror::new();
+
+        let update = [0xa5, 1, 8, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0];
+        assert!(matches!(
+            mirror.read_frame(&mut &update[..]),
+            Err(MirrorError::MissingHeader)
+        ));
+
+        assert!(matches!(
+            mirror.read_frame(&mut &[0xa5, 0, 6, 0, 0, 0, 4, 0, 4, 0, 3, 1][..]),
+            Err(MirrorError::UnsupportedFormat)
+        ));
+
+        // Depths that don't divide a byte evenly are rejected
+        assert!(matches!(
+            mirror.read_frame(&mut &[0xa5, 0, 6, 0, 0, 0, 4, 0, 4, 0, 3, 0][..]),
+            Err(MirrorError::UnsupportedFormat)
+        ));
+
+        let header = [0xa5, 0, 6, 0, 0, 0, 4, 0, 4, 0, 1, 0];
+        assert_eq!(
+            mirror.read_frame(&mut &header[..]).unwrap(),
+            Some(Frame::Header(Size::new(4, 4)))
+        );
+
+        assert!(matches!(
+            mirror.read_frame(&mut &[0x5a][..]),
+            Err(MirrorError::InvalidFrame)
+        ));
+        assert!(matches!(
+            mirror.read_frame(&mut &header[..8]),
+            Err(MirrorError::InvalidFrame)
+        ));
+
+        // Dirty rectangle outside the display
+        assert!(matches!(
+            mirror.read_frame(&mut &[0xa5, 1, 8, 0, 0, 0, 3, 0, 0, 0, 2, 0, 1, 0][..]),
+            Err(MirrorError::InvalidFrame)
+        ));
+
+        // Span outside the display
+        assert!(matches!(
+            mirror.read_frame(
+                &mut &[0xa5, 1, 15, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 1, 0, 0, 0, 1, 0, 0, 1][..]
+            ),
+            Err(MirrorError::Rle(crate::RleError::OutOfBounds {
+                page: 1,
+                ..
+            }))
+        ));
+
+        // Span inside the display but outside the reported area
+        assert!(matches!(
+            mirror.read_frame(
+                &mut &[0xa5, 1, 15, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 2, 0, 1, 0, 0, 1][..]
+            ),
+            Err(MirrorError::Rle(crate::RleError::OutOfBounds {
+                page: 0,
+                column: 2,
+                length: 1
+            }))
+        ));
+        assert_eq!(mirror.get_raw(Point::new(2, 0)), Some(0));
+
+        assert_eq!(
+            mirror.read_frame(&mut &update[..]).unwrap(),
+            Some(Frame::Update(Rectangle::new(
+                Point::zero(),
+                Size::new(1, 1)
+            )))
+        );
+    }
+}
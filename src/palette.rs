@@ -0,0 +1,403 @@
+use crate::{block_iterator::BlockIterator, pixels::from_storage, PackedBuffer};
+use core::convert::Infallible;
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::{
+        raw::RawData, Bgr555, Bgr565, Bgr666, Bgr888, BinaryColor, Gray2, Gray4, Gray8, GrayColor,
+        IntoStorage, PixelColor, Rgb555, Rgb565, Rgb666, Rgb888, RgbColor,
+    },
+    primitives::Rectangle,
+    Pixel,
+};
+
+/// A measure of how different two colors are, used to pick the nearest [`Palette`] entry.
+pub trait ColorDistance {
+    /// Distance between two colors. Identical colors must have a distance of zero.
+    fn distance(self, other: Self) -> u32;
+}
+
+macro_rules! impl_rgb_distance {
+    ($($type:ident),*) => {
+        $(impl ColorDistance for $type {
+            fn distance(self, other: Self) -> u32 {
+                let r = u32::from(self.r().abs_diff(other.r()));
+                let g = u32::from(self.g().abs_diff(other.g()));
+                let b = u32::from(self.b().abs_diff(other.b()));
+
+                r * r + g * g + b * b
+            }
+        })*
+    };
+}
+
+impl_rgb_distance!(Rgb555, Bgr555, Rgb565, Bgr565, Rgb666, Bgr666, Rgb888, Bgr888);
+
+macro_rules! impl_gray_distance {
+    ($($type:ident),*) => {
+        $(impl ColorDistance for $type {
+            fn distance(self, other: Self) -> u32 {
+                u32::from(self.luma().abs_diff(other.luma()))
+            }
+        })*
+    };
+}
+
+impl_gray_distance!(Gray2, Gray4, Gray8);
+
+impl ColorDistance for BinaryColor {
+    fn distance(self, other: Self) -> u32 {
+        u32::from(self != other)
+    }
+}
+
+/// A lookup table of up to `K` colors.
+///
+/// Incoming colors are mapped to the index of the nearest entry as measured by [`ColorDistance`].
+/// If several entries are equally near, the one with the lowest index is used.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Palette<C, const K: usize> {
+    colors: [C; K],
+}
+
+impl<C, const K: usize> Palette<C, K>
+where
+    C: PixelColor + ColorDistance,
+{
+    pub const fn new(colors: [C; K]) -> Self {
+        assert!(K > 0, "Palette must contain at least one color");
+
+        Self { colors }
+    }
+
+    pub fn colors(&self) -> &[C; K] {
+        &self.colors
+    }
+
+    /// Find the index of the entry nearest to the given color.
+    pub fn nearest(&self, color: C) -> usize {
+        let mut best = 0;
+        let mut best_distance = u32::MAX;
+
+        for (idx, entry) in self.colors.iter().enumerate() {
+            let distance = entry.distance(color);
+
+            if distance < best_distance {
+                best = idx;
+                best_distance = distance;
+            }
+
+            if distance == 0 {
+                break;
+            }
+        }
+
+        best
+    }
+
+    /// Get the color at the given index.
+    ///
+    /// Returns `None` if the index is outside the palette.
+    pub fn get(&self, index: usize) -> Option<C> {
+        self.colors.get(index).copied()
+    }
+}
+
+/// A [`PackedBuffer`] that stores palette indices of type `S` but is drawn to with colors of
+/// type `C`.
+///
+/// This allows e.g. a 4 level grayscale or 4 color e-paper panel to be drawn to with `Rgb888`
+/// colors, which are mapped to the nearest of the `K <= 2^bpp` entries in the palette.
+///
+/// ```rust
+/// use embedded_graphics_core::{pixelcolor::{Gray2, Rgb888, RgbColor}, prelude::*};
+/// use packed_display_buffer::{buffer_size, Palette, PaletteBuffer};
+///
+/// let palette = Palette::new([Rgb888::WHITE, Rgb888::BLACK, Rgb888::RED]);
+///
+/// let mut display =
+///     PaletteBuffer::<128, 64, { buffer_size::<Gray2>(128, 64) }, Gray2, _, 3>::new(palette);
+///
+/// display.set_pixel(Point::new(10, 10), Rgb888::new(200, 10, 10));
+///
+/// assert_eq!(display.get_pixel(Point::new(10, 10)), Some(Rgb888::RED));
+/// assert_eq!(display.as_packed().get_pixel(Point::new(10, 10)), Some(Gray2::new(2)));
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct PaletteBuffer<const W: u32, const H: u32, const N: usize, S, C, const K: usize> {
+    buffer: PackedBuffer<W, H, N, S>,
+    palette: Palette<C, K>,
+}
+
+impl<const W: u32, const H: u32, const N: usize, S, C, const K: usize>
+    PaletteBuffer<W, H, N, S, C, K>
+where
+    S: PixelColor + IntoStorage<Storage = u8> + From<S::Raw>,
+    C: PixelColor + ColorDistance,
+{
+    pub const fn new(palette: Palette<C, K>) -> Self {
+        assert!(
+            K <= 1 << S::Raw::BITS_PER_PIXEL,
+            "Palette has more entries than the storage color can index"
+        );
+
+        Self {
+            buffer: PackedBuffer::new(),
+            palette,
+        }
+    }
+
+    pub fn palette(&self) -> &Palette<C, K> {
+        &self.palette
+    }
+
+    /// The underlying buffer of palette indices.
+    pub fn as_packed(&self) -> &PackedBuffer<W, H, N, S> {
+        &self.buffer
+    }
+
+    /// Set an individual pixel to the palette entry nearest to `color`.
+    ///
+    /// Any given pixels that are outside the display area will be ignored.
+    pub fn set_pixel(&mut self, point: Point, color: C) {
+        let index = index_color(&self.palette, color);
+
+        self.buffer.set_pixel(point, index);
+    }
+
+    /// Get the palette color of an individual pixel.
+    ///
+    /// Returns `None` if the point is outside the display area.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer holds an index outside the palette, which means it's been corrupted.
+    pub fn get_pixel(&self, point: Point) -> Option<C> {
+        self.buffer.get_pixel(point).map(|index| self.color(index))
+    }
+
+    /// Get an iterator over every pixel in the buffer as palette colors, in row-major order.
+    ///
+    /// # Panics
+    ///
+    /// The iterator panics if the buffer holds an index outside the palette.
+    pub fn pixels(&self) -> impl Iterator<Item = Pixel<C>> + '_ {
+        self.buffer
+            .pixels()
+            .map(|Pixel(point, index)| Pixel(point, self.color(index)))
+    }
+
+    /// Look up the palette color of a stored index.
+    fn color(&self, index: S) -> C {
+        self.palette
+            .get(index.into_storage().into())
+            .expect("Buffer holds an index outside the palette")
+    }
+
+    pub fn clear_active_area(&mut self) {
+        self.buffer.clear_active_area();
+    }
+
+    pub fn active_area(&self) -> Rectangle {
+        self.buffer.active_area()
+    }
+
+    /// Get an iterator over blocks of palette indices in the active area of the display buffer.
+    ///
+    /// See [`PackedBuffer::active_blocks`].
+    pub fn active_blocks(&self) -> BlockIterator<'_> {
+        self.buffer.active_blocks()
+    }
+}
+
+/// Map a color to the storage color holding its nearest palette index.
+fn index_color<S, C, const K: usize>(palette: &Palette<C, K>, color: C) -> S
+where
+    S: PixelColor + From<S::Raw>,
+    C: PixelColor + ColorDistance,
+{
+    from_storage(palette.nearest(color) as u8)
+}
+
+impl<const W: u32, const H: u32, const N: usize, S, C, const K: usize> AsRef<[u8]>
+    for PaletteBuffer<W, H, N, S, C, K>
+{
+    fn as_ref(&self) -> &[u8] {
+        self.buffer.as_ref()
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, S, C, const K: usize> OriginDimensions
+    for PaletteBuffer<W, H, N, S, C, K>
+{
+    fn size(&self) -> Size {
+        self.buffer.size()
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, S, C, const K: usize> DrawTarget
+    for PaletteBuffer<W, H, N, S, C, K>
+where
+    S: PixelColor + IntoStorage<Storage = u8> + From<S::Raw>,
+    C: PixelColor + ColorDistance,
+{
+    type Color = C;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let palette = &self.palette;
+
+        self.buffer.draw_iter(
+            pixels
+                .into_iter()
+                .map(|Pixel(point, color)| Pixel(point, index_color(palette, color))),
+        )
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let palette = &self.palette;
+
+        // Images tend to contain long runs of the same color, so remember the last lookup instead
+        // of searching the palette for every pixel.
+        let mut last: Option<(C, S)> = None;
+
+        let colors = colors.into_iter().map(|color| match last {
+            Some((last_color, index)) if last_color == color => index,
+            _ => {
+                let index = index_color(palette, color);
+                last = Some((color, index));
+                index
+            }
+        });
+
+        self.buffer.fill_contiguous(area, colors)
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let index = index_color(&self.palette, color);
+
+        self.buffer.fill_solid(area, index)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        let index = index_color(&self.palette, color);
+
+        self.buffer.clear(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_size;
+    use embedded_graphics_core::primitives::PointsIter;
+
+    type Epd = PaletteBuffer<8, 8, { buffer_size::<Gray2>(8, 8) }, Gray2, Rgb888, 4>;
+
+    const PALETTE: Palette<Rgb888, 4> =
+        Palette::new([Rgb888::WHITE, Rgb888::BLACK, Rgb888::RED, Rgb888::YELLOW]);
+
+    #[test]
+    fn nearest() {
+        assert_eq!(PALETTE.nearest(Rgb888::WHITE), 0);
+        assert_eq!(PALETTE.nearest(Rgb888::new(20, 20, 20)), 1);
+        assert_eq!(PALETTE.nearest(Rgb888::new(180, 40, 0)), 2);
+        assert_eq!(PALETTE.nearest(Rgb888::new(250, 220, 30)), 3);
+    }
+
+    #[test]
+    fn nearest_tie_uses_lowest_index() {
+        let palette = Palette::new([Gray8::new(0), Gray8::new(100), Gray8::new(200)]);
+
+        assert_eq!(palette.nearest(Gray8::new(150)), 1);
+    }
+
+    #[test]
+    fn get() {
+        assert_eq!(PALETTE.get(2), Some(Rgb888::RED));
+        assert_eq!(PALETTE.get(4), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "index outside the palette")]
+    fn invalid_index() {
+        let palette = Palette::new([Rgb888::WHITE, Rgb888::BLACK, Rgb888::RED]);
+        let mut display =
+            PaletteBuffer::<8, 8, { buffer_size::<Gray2>(8, 8) }, Gray2, _, 3>::new(palette);
+
+        // Index 3 can be stored in 2 bits but has no palette entry
+        display.buffer.set_pixel(Point::new(1, 1), Gray2::new(3));
+
+        display.get_pixel(Point::new(1, 1));
+    }
+
+    #[test]
+    fn readback() {
+        let mut display = Epd::new(PALETTE);
+
+        display.set_pixel(Point::new(1, 2), Rgb888::new(255, 10, 10));
+        display.set_pixel(Point::new(7, 7), Rgb888::new(10, 10, 10));
+
+        assert_eq!(display.get_pixel(Point::new(1, 2)), Some(Rgb888::RED));
+        assert_eq!(display.get_pixel(Point::new(7, 7)), Some(Rgb888::BLACK));
+        assert_eq!(display.get_pixel(Point::new(0, 0)), Some(Rgb888::WHITE));
+        assert_eq!(display.get_pixel(Point::new(8, 0)), None);
+
+        assert_eq!(
+            display.as_packed().get_pixel(Point::new(1, 2)),
+            Some(Gray2::new(2))
+        );
+    }
+
+    #[test]
+    fn pixels() {
+        let mut display = Epd::new(PALETTE);
+
+        let area = Rectangle::new(Point::new(2, 3), Size::new(3, 2));
+
+        display.fill_solid(&area, Rgb888::YELLOW).unwrap();
+
+        for Pixel(point, color) in display.pixels() {
+            let expected = if area.contains(point) {
+                Rgb888::YELLOW
+            } else {
+                Rgb888::WHITE
+            };
+
+            assert_eq!(color, expected, "{:?}", point);
+        }
+
+        assert_eq!(display.pixels().count(), 64);
+    }
+
+    #[test]
+    fn contiguous() {
+        let mut display = Epd::new(PALETTE);
+
+        let area = Rectangle::new(Point::new(-1, 0), Size::new(4, 2));
+        let colors = [
+            Rgb888::RED,
+            Rgb888::RED,
+            Rgb888::BLACK,
+            Rgb888::BLACK,
+            Rgb888::YELLOW,
+            Rgb888::RED,
+            Rgb888::RED,
+            Rgb888::WHITE,
+        ];
+
+        display.fill_contiguous(&area, colors).unwrap();
+
+        for (point, color) in area.points().zip(colors) {
+            if point.x >= 0 {
+                assert_eq!(display.get_pixel(point), Some(color), "{:?}", point);
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::PackedBuffer;
+use embedded_graphics_core::{
+    pixelcolor::{raw::RawData, IntoStorage, PixelColor},
+    primitives::{rectangle, PointsIter},
+    Pixel,
+};
+
+/// An iterator over every pixel in a [`PackedBuffer`], in row-major order.
+///
+/// Created by [`PackedBuffer::pixels`].
+#[derive(Debug)]
+pub struct Pixels<'a, const W: u32, const H: u32, const N: usize, C> {
+    buffer: &'a PackedBuffer<W, H, N, C>,
+    points: rectangle::Points,
+}
+
+impl<'a, const W: u32, const H: u32, const N: usize, C> Pixels<'a, W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+{
+    pub(crate) fn new(buffer: &'a PackedBuffer<W, H, N, C>) -> Self {
+        Self {
+            buffer,
+            points: buffer.area.points(),
+        }
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> Iterator for Pixels<'_, W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+{
+    type Item = Pixel<C>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let point = self.points.next()?;
+
+        let color = self.buffer.get_pixel_unchecked(point);
+
+        Some(Pixel(point, color))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.points.size_hint()
+    }
+}
+
+/// Convert a raw storage value read from the buffer back into a color.
+pub(crate) fn from_storage<C>(value: u8) -> C
+where
+    C: PixelColor + From<C::Raw>,
+{
+    C::from(C::Raw::from_u32(value.into()))
+}
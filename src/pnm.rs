@@ -0,0 +1,398 @@
+use crate::{
+    byte_direction::Layout,
+    mask::{slot_bits, slot_mask},
+    PackedBuffer, PackedSlice,
+};
+use core::fmt::{self, Write};
+use embedded_graphics_core::{
+    geometry::{Dimensions, Point, Size},
+    pixelcolor::{raw::RawData, IntoStorage, PixelColor},
+};
+
+/// A destination for binary image data.
+///
+/// This allows images to be exported without an allocator, e.g. straight to a UART or file.
+pub trait ByteSink {
+    /// Error returned when bytes can't be written.
+    type Error;
+
+    /// Write all the given bytes.
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// A [`ByteSink`] that writes into a fixed size slice.
+#[derive(Debug)]
+pub struct SliceSink<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceSink<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// The bytes written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// Error returned when a [`SliceSink`] runs out of space.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BufferFull;
+
+impl ByteSink for SliceSink<'_> {
+    type Error = BufferFull;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        let dest = self
+            .buf
+            .get_mut(self.len..self.len + bytes.len())
+            .ok_or(BufferFull)?;
+
+        dest.copy_from_slice(bytes);
+        self.len += bytes.len();
+
+        Ok(())
+    }
+}
+
+/// A [`ByteSink`] that writes to a [`std::io::Write`] implementation, e.g. a file or serial port.
+#[cfg(any(test, feature = "std"))]
+#[derive(Debug)]
+pub struct IoSink<W> {
+    writer: W,
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<W: std::io::Write> IoSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Get the underlying writer back.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<W: std::io::Write> ByteSink for IoSink<W> {
+    type Error = std::io::Error;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.writer.write_all(bytes)
+    }
+}
+
+/// Formats headers directly into a [`ByteSink`], keeping the sink's error.
+struct HeaderWriter<'a, S: ByteSink> {
+    sink: &'a mut S,
+    error: Option<S::Error>,
+}
+
+impl<S: ByteSink> Write for HeaderWriter<'_, S> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.sink.write_bytes(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+
+            fmt::Error
+        })
+    }
+}
+
+/// Raw image data with a known layout.
+struct Image<'a> {
+    data: &'a [u8],
+    size: Size,
+    layout: Layout,
+    bpp: u32,
+}
+
+impl<'a> Image<'a> {
+    fn new<C: PixelColor>(data: &'a [u8], size: Size, layout: Layout) -> Self {
+        let bpp = C::Raw::BITS_PER_PIXEL as u32;
+
+        assert!(
+            (1..=8).contains(&bpp),
+            "Only pixel formats with 1 to 8bpp can be exported"
+        );
+        assert!(
+            data.len() >= layout.len(size.width, size.height, slot_bits(bpp as usize)),
+            "Image data is too short for its size"
+        );
+
+        Self {
+            data,
+            size,
+            layout,
+            bpp,
+        }
+    }
+
+    fn max(&self) -> u8 {
+        slot_mask(self.bpp)
+    }
+
+    fn get(&self, x: u32, y: u32) -> u8 {
+        let slot = slot_bits(self.bpp as usize);
+
+        let (idx, shift) =
+            self.layout
+                .locate(self.size.width, Point::new(x as i32, y as i32), slot);
+
+        (self.data[idx] >> shift) & self.max()
+    }
+
+    /// Convert a pixel value to a PBM bit, where 1 is black.
+    fn pbm_bit(&self, x: u32, y: u32) -> u8 {
+        u8::from(self.get(x, y) == 0)
+    }
+}
+
+/// Maximum line length allowed by the plain PNM formats.
+const LINE_LENGTH: usize = 70;
+
+/// Write raw image data of color `C` as a binary PNM image.
+///
+/// 1bpp images are written as a P4 bitmap (PBM) and gray depths as a P5 graymap (PGM). Raw pixel
+/// values are written unchanged, so `0` is black and the largest value is white. This means that
+/// `BinaryColor::On` pixels are exported as white, as they'd appear on an OLED panel.
+///
+/// # Panics
+///
+/// Panics if `data` is too short for an image of the given size and layout.
+pub fn write_pnm<C, S>(
+    data: &[u8],
+    size: Size,
+    layout: Layout,
+    sink: &mut S,
+) -> Result<(), S::Error>
+where
+    C: PixelColor,
+    S: ByteSink,
+{
+    let image = Image::new::<C>(data, size, layout);
+    let Size { width, height } = size;
+
+    let mut header = HeaderWriter { sink, error: None };
+
+    let result = if image.bpp == 1 {
+        write!(header, "P4\n{} {}\n", width, height)
+    } else {
+        write!(header, "P5\n{} {}\n{}\n", width, height, image.max())
+    };
+
+    if let (Err(_), Some(e)) = (result, header.error) {
+        return Err(e);
+    }
+
+    for y in 0..height {
+        if image.bpp == 1 {
+            // Rows are packed MSB first and padded to a whole byte
+            for byte_x in (0..width).step_by(u8::BITS as usize) {
+                let byte = (byte_x..(byte_x + u8::BITS).min(width)).fold(0u8, |byte, x| {
+                    byte | image.pbm_bit(x, y) << (7 - (x - byte_x))
+                });
+
+                sink.write_bytes(&[byte])?;
+            }
+        } else {
+            for x in 0..width {
+                sink.write_bytes(&[image.get(x, y)])?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Write raw image data of color `C` as a plain (ASCII) PNM image.
+///
+/// This is the same as [`write_pnm`] but writes a P1 bitmap or P2 graymap, which can be embedded
+/// in text such as log output or a test snapshot.
+///
+/// # Panics
+///
+/// Panics if `data` is too short for an image of the given size and layout.
+pub fn write_pnm_ascii<C, W>(data: &[u8], size: Size, layout: Layout, out: &mut W) -> fmt::Result
+where
+    C: PixelColor,
+    W: Write,
+{
+    let image = Image::new::<C>(data, size, layout);
+    let Size { width, height } = size;
+
+    if image.bpp == 1 {
+        write!(out, "P1\n{} {}\n", width, height)?;
+    } else {
+        write!(out, "P2\n{} {}\n{}\n", width, height, image.max())?;
+    }
+
+    for y in 0..height {
+        // Each row starts on a new line, wrapped to keep lines under the length limit
+        let mut line = 0;
+
+        for x in 0..width {
+            let value = if image.bpp == 1 {
+                image.pbm_bit(x, y)
+            } else {
+                image.get(x, y)
+            };
+
+            let len = if image.bpp == 1 { 1 } else { 4 };
+
+            if line + len > LINE_LENGTH {
+                out.write_char('\n')?;
+                line = 0;
+            }
+
+            if image.bpp == 1 {
+                write!(out, "{}", value)?;
+            } else {
+                write!(out, "{:>3} ", value)?;
+            }
+
+            line += len;
+        }
+
+        out.write_char('\n')?;
+    }
+
+    Ok(())
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    /// Write the buffer contents as a binary PBM or PGM image.
+    ///
+    /// See [`write_pnm`] for details of the format.
+    pub fn write_pnm<S: ByteSink>(&self, sink: &mut S) -> Result<(), S::Error> {
+        write_pnm::<C, S>(&self.buf, self.area.size, Layout::Vertical, sink)
+    }
+
+    /// Write the buffer contents as a plain (ASCII) PBM or PGM image.
+    ///
+    /// See [`write_pnm_ascii`] for details of the format.
+    pub fn write_pnm_ascii<F: Write>(&self, out: &mut F) -> fmt::Result {
+        write_pnm_ascii::<C, F>(&self.buf, self.area.size, Layout::Vertical, out)
+    }
+}
+
+impl<C> PackedSlice<'_, C>
+where
+    C: PixelColor,
+{
+    /// Write the slice contents as a binary PBM or PGM image.
+    ///
+    /// See [`write_pnm`] for details of the format.
+    pub fn write_pnm<S: ByteSink>(&self, sink: &mut S) -> Result<(), S::Error> {
+        write_pnm::<C, S>(
+            self.data(),
+            self.bounding_box().size,
+            Layout::Vertical,
+            sink,
+        )
+    }
+
+    /// Write the slice contents as a plain (ASCII) PBM or PGM image.
+    ///
+    /// See [`write_pnm_ascii`] for details of the format.
+    pub fn write_pnm_ascii<F: Write>(&self, out: &mut F) -> fmt::Result {
+        write_pnm_ascii::<C, F>(self.data(), self.bounding_box().size, Layout::Vertical, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_size;
+    use embedded_graphics::pixelcolor::{BinaryColor, Gray2};
+
+    #[test]
+    fn pbm() {
+        let mut display =
+            PackedBuffer::<10, 3, { buffer_size::<BinaryColor>(10, 3) }, BinaryColor>::new();
+
+        display.set_pixel(Point::new(0, 0), BinaryColor::On);
+        display.set_pixel(Point::new(9, 1), BinaryColor::On);
+
+        let mut buf = [0u8; 32];
+        let mut sink = SliceSink::new(&mut buf);
+
+        display.write_pnm(&mut sink).unwrap();
+
+        assert_eq!(sink.written(), b"P4\n10 3\n\x7f\xc0\xff\x80\xff\xc0");
+
+        let mut ascii = String::new();
+        display.write_pnm_ascii(&mut ascii).unwrap();
+
+        assert_eq!(ascii, "P1\n10 3\n0111111111\n1111111110\n1111111111\n");
+    }
+
+    #[test]
+    fn pgm() {
+        let mut display = PackedBuffer::<3, 5, { buffer_size::<Gray2>(3, 5) }, Gray2>::new();
+
+        display.set_pixel(Point::new(1, 0), Gray2::new(1));
+        display.set_pixel(Point::new(2, 4), Gray2::new(3));
+
+        let mut buf = [0u8; 32];
+        let mut sink = SliceSink::new(&mut buf);
+
+        display.as_slice().write_pnm(&mut sink).unwrap();
+
+        assert_eq!(
+            sink.written(),
+            b"P5\n3 5\n3\n\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03"
+        );
+
+        let mut ascii = String::new();
+        display.write_pnm_ascii(&mut ascii).unwrap();
+
+        assert_eq!(
+            ascii,
+            "P2\n3 5\n3\n  0   1   0 \n  0   0   0 \n  0   0   0 \n  0   0   0 \n  0   0   3 \n"
+        );
+    }
+
+    #[test]
+    fn horizontal_layout() {
+        // 2bpp, 5 pixels per row padded to 2 bytes
+        let data = [0b00_01_10_11, 0b01_000000, 0b11_00_00_00, 0b00_000000];
+
+        let mut buf = [0u8; 32];
+        let mut sink = SliceSink::new(&mut buf);
+
+        write_pnm::<Gray2, _>(&data, Size::new(5, 2), Layout::Horizontal, &mut sink).unwrap();
+
+        assert_eq!(
+            sink.written(),
+            b"P5\n5 2\n3\n\x00\x01\x02\x03\x01\x03\x00\x00\x00\x00"
+        );
+    }
+
+    #[test]
+    fn wrap_long_lines() {
+        let display = PackedBuffer::<100, 1, 100, BinaryColor>::new();
+
+        let mut ascii = String::new();
+        display.write_pnm_ascii(&mut ascii).unwrap();
+
+        assert!(ascii.lines().all(|line| line.len() <= LINE_LENGTH));
+        assert_eq!(ascii.lines().skip(2).map(str::len).sum::<usize>(), 100);
+    }
+
+    #[test]
+    fn sink_full() {
+        let display = PackedBuffer::<16, 16, 32, BinaryColor>::new();
+
+        let mut buf = [0u8; 16];
+        let mut sink = SliceSink::new(&mut buf);
+
+        assert_eq!(display.write_pnm(&mut sink), Err(BufferFull));
+    }
+}
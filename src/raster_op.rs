@@ -0,0 +1,181 @@
+use core::ops::{BitAnd, BitOr, BitXor, Not};
+
+/// How new pixel data is combined with the existing contents of a buffer.
+///
+/// Operations are applied bitwise to the raw storage value of each pixel. For `BinaryColor` this
+/// means e.g. drawing `On` with [`RasterOp::Xor`] inverts pixels and drawing `Off` leaves them
+/// unchanged, so drawing the same shape twice in XOR mode restores the original background.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RasterOp {
+    /// Overwrite existing pixels.
+    #[default]
+    Replace,
+
+    /// `dst | src`
+    Or,
+
+    /// `dst & src`
+    And,
+
+    /// `dst ^ src`
+    Xor,
+
+    /// `dst & !src`
+    AndNot,
+}
+
+impl RasterOp {
+    /// Combine `src` with `dst`, changing only the bits set in `mask`.
+    #[inline]
+    pub fn apply(self, dst: u8, src: u8, mask: u8) -> u8 {
+        self.combine(dst, src, mask)
+    }
+
+    /// Combine a whole machine word at once, as with [`apply`](Self::apply).
+    #[inline]
+    pub(crate) fn apply_word(self, dst: usize, src: usize, mask: usize) -> usize {
+        self.combine(dst, src, mask)
+    }
+
+    #[inline]
+    fn combine<T>(self, dst: T, src: T, mask: T) -> T
+    where
+        T: Copy + BitAnd<Output = T> + BitOr<Output = T> + BitXor<Output = T> + Not<Output = T>,
+    {
+        let result = match self {
+            RasterOp::Replace => src,
+            RasterOp::Or => dst | src,
+            RasterOp::And => dst & src,
+            RasterOp::Xor => dst ^ src,
+            RasterOp::AndNot => dst & !src,
+        };
+
+        (dst & !mask) | (result & mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer_size, PackedBuffer};
+    use embedded_graphics_core::{
+        geometry::{Point, Size},
+        pixelcolor::{BinaryColor, Gray2},
+        primitives::{PointsIter, Rectangle},
+        Pixel,
+    };
+
+    #[test]
+    fn apply() {
+        let dst = 0b1100_1100;
+        let src = 0b1010_1010;
+        let mask = 0b0000_1111;
+
+        assert_eq!(RasterOp::Replace.apply(dst, src, mask), 0b1100_1010);
+        assert_eq!(RasterOp::Or.apply(dst, src, mask), 0b1100_1110);
+        assert_eq!(RasterOp::And.apply(dst, src, mask), 0b1100_1000);
+        assert_eq!(RasterOp::Xor.apply(dst, src, mask), 0b1100_0110);
+        assert_eq!(RasterOp::AndNot.apply(dst, src, mask), 0b1100_0100);
+    }
+
+    #[test]
+    fn xor_twice_restores_background() {
+        let mut display = PackedBuffer::<16, 16, 32, BinaryColor>::new();
+
+        let background = Rectangle::new(Point::new(2, 3), Size::new(9, 7));
+        let cursor = Rectangle::new(Point::new(5, 1), Size::new(6, 12));
+
+        display.fill_rect(&background, BinaryColor::On);
+
+        let before = display.as_ref().to_vec();
+
+        display.set_raster_op(RasterOp::Xor);
+
+        display.fill_rect(&cursor, BinaryColor::On);
+        assert_ne!(display.as_ref(), &before[..]);
+
+        display.fill_rect(&cursor, BinaryColor::On);
+        assert_eq!(display.as_ref(), &before[..]);
+
+        for point in cursor.points() {
+            display.set_pixel(point, BinaryColor::On);
+        }
+        assert_ne!(display.as_ref(), &before[..]);
+
+        for point in cursor.points() {
+            display.set_pixel(point, BinaryColor::On);
+        }
+        assert_eq!(display.as_ref(), &before[..]);
+    }
+
+    #[test]
+    fn fill_matches_set_pixel() {
+        let ops = [
+            RasterOp::Replace,
+            RasterOp::Or,
+            RasterOp::And,
+            RasterOp::Xor,
+            RasterOp::AndNot,
+        ];
+
+        let background = Rectangle::new(Point::new(0, 2), Size::new(8, 13));
+        let area = Rectangle::new(Point::new(3, 1), Size::new(4, 18));
+
+        for op in ops {
+            let mut fill = PackedBuffer::<8, 20, { buffer_size::<Gray2>(8, 20) }, Gray2>::new();
+            fill.fill_rect(&background, Gray2::new(0b10));
+
+            let mut pixels = PackedBuffer::<8, 20, { buffer_size::<Gray2>(8, 20) }, Gray2>::new();
+            pixels.fill_rect(&background, Gray2::new(0b10));
+
+            fill.set_raster_op(op);
+            pixels.set_raster_op(op);
+
+            fill.fill_rect(&area, Gray2::new(0b11));
+
+            for point in area.points() {
+                pixels.set_pixel(point, Gray2::new(0b11));
+            }
+
+            assert_eq!(fill, pixels, "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn invert_rect() {
+        let mut display = PackedBuffer::<16, 24, { buffer_size::<Gray2>(16, 24) }, Gray2>::new();
+        display.fill_rect(
+            &Rectangle::new(Point::new(0, 0), Size::new(16, 6)),
+            Gray2::new(0b01),
+        );
+        display.clear_active_area();
+
+        let area = Rectangle::new(Point::new(-3, 2), Size::new(10, 19));
+
+        display.invert_rect(&area);
+
+        for Pixel(point, color) in display.pixels() {
+            let background = if point.y < 6 { 0b01 } else { 0b00 };
+
+            let expected = if area.contains(point) {
+                0b11 - background
+            } else {
+                background
+            };
+
+            assert_eq!(color, Gray2::new(expected), "{:?}", point);
+        }
+
+        assert_eq!(
+            display.active_area(),
+            Rectangle::new(Point::new(0, 2), Size::new(7, 19))
+        );
+
+        // Inverting twice restores the original contents, regardless of raster op
+        display.set_raster_op(RasterOp::Or);
+        display.invert_rect(&area);
+        assert!(display.pixels().all(|Pixel(point, color)| {
+            color == Gray2::new(if point.y < 6 { 0b01 } else { 0b00 })
+        }));
+    }
+}
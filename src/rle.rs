@@ -0,0 +1,452 @@
+//! Run-length encoding of buffer contents for low bandwidth links.
+//!
+//! [`PackedBuffer::write_rle`] encodes each block yielded by
+//! [`active_blocks`](PackedBuffer::active_blocks) as a span, which [`PackedBuffer::apply_rle`]
+//! writes into another buffer of the same type. Each span starts with a header of three
+//! little endian `u16`s:
+//!
+//! | Offset | Size | Contents                                   |
+//! |--------|------|--------------------------------------------|
+//! | 0      | 2    | Page, i.e. the row of bytes in the buffer  |
+//! | 2      | 2    | Column of the first byte                   |
+//! | 4      | 2    | Number of bytes in the span                |
+//! | 6      |      | The span bytes, compressed with [`encode`] |
+//!
+//! The compression is the PackBits scheme used by TIFF and MacPaint. Each packet starts with a
+//! control byte `n`:
+//!
+//! - `0..=127`: the next `n + 1` bytes are copied literally.
+//! - `129..=255`: the next byte is repeated `257 - n` times.
+//! - `128`: no operation.
+//!
+//! Display contents usually have long runs of blank or filled bytes, which compress to two bytes
+//! per 128 byte run. Incompressible data grows by at most one byte in 128.
+
+use crate::{pnm::ByteSink, PackedBuffer};
+use embedded_graphics_core::{
+    geometry::{Point, Size},
+    pixelcolor::{IntoStorage, PixelColor},
+    primitives::Rectangle,
+};
+
+/// Longest run or literal that fits in one packet.
+const MAX_PACKET: usize = 128;
+
+/// Length of a span header.
+const SPAN_HEADER_LEN: usize = 6;
+
+/// Error returned when RLE data can't be decoded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RleError {
+    /// The data ended in the middle of a span or packet.
+    Truncated,
+
+    /// A packet decodes to more bytes than are left in the span.
+    Overrun,
+
+    /// A span doesn't fit inside the buffer, or lies outside the area it was sent for.
+    OutOfBounds {
+        /// Page of the span.
+        page: u16,
+
+        /// Column of the first byte in the span.
+        column: u16,
+
+        /// Number of bytes in the span.
+        length: u16,
+    },
+}
+
+/// Compress `data` with PackBits.
+pub fn encode<S: ByteSink>(data: &[u8], sink: &mut S) -> Result<(), S::Error> {
+    let mut rest = data;
+
+    while !rest.is_empty() {
+        let run = run_len(rest);
+
+        if run > 1 {
+            sink.write_bytes(&[(257 - run) as u8, rest[0]])?;
+            rest = &rest[run..];
+
+            continue;
+        }
+
+        // Collect literals until the next run worth encoding. Runs of two are only worth breaking
+        // a literal for if they'd start a new packet anyway.
+        let mut len = 1;
+
+        while len < rest.len().min(MAX_PACKET) && run_len(&rest[len..]) < 3 {
+            len += 1;
+        }
+
+        sink.write_bytes(&[(len - 1) as u8])?;
+        sink.write_bytes(&rest[..len])?;
+        rest = &rest[len..];
+    }
+
+    Ok(())
+}
+
+/// Decompress PackBits data, filling all of `output`.
+///
+/// Returns the number of input bytes used.
+pub fn decode(input: &[u8], output: &mut [u8]) -> Result<usize, RleError> {
+    let mut pos = 0;
+    let mut written = 0;
+
+    while written < output.len() {
+        let control = *input.get(pos).ok_or(RleError::Truncated)?;
+        pos += 1;
+
+        match control {
+            0..=127 => {
+                let len = usize::from(control) + 1;
+
+                let literal = input.get(pos..pos + len).ok_or(RleError::Truncated)?;
+                output
+                    .get_mut(written..written + len)
+                    .ok_or(RleError::Overrun)?
+                    .copy_from_slice(literal);
+
+                pos += len;
+                written += len;
+            }
+            128 => {}
+            129..=255 => {
+                let len = 257 - usize::from(control);
+
+                let value = *input.get(pos).ok_or(RleError::Truncated)?;
+                output
+                    .get_mut(written..written + len)
+                    .ok_or(RleError::Overrun)?
+                    .fill(value);
+
+                pos += 1;
+                written += len;
+            }
+        }
+    }
+
+    Ok(pos)
+}
+
+/// Length of the run of identical bytes at the start of `data`, up to the packet limit.
+fn run_len(data: &[u8]) -> usize {
+    data.first().map_or(0, |first| {
+        data.iter()
+            .take(MAX_PACKET)
+            .take_while(|byte| *byte == first)
+            .count()
+    })
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    /// Fails to compile if the display is too large for the `u16` fields of span headers and
+    /// mirror frames.
+    pub(crate) const FITS_U16: () = assert!(
+        W <= u16::MAX as u32 && H <= u16::MAX as u32,
+        "Display width and height must fit in a u16 for RLE spans and mirroring"
+    );
+
+    /// Encode the active blocks of the buffer as run-length encoded spans.
+    ///
+    /// See the [`rle`](crate::rle) module for details of the format. Nothing is written if the
+    /// active area is empty. The active area isn't cleared, so call
+    /// [`clear_active_area`](Self::clear_active_area) once the data has been sent.
+    ///
+    /// ```rust
+    /// use embedded_graphics_core::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle};
+    /// use packed_display_buffer::{PackedBuffer, SliceSink};
+    ///
+    /// let mut device = PackedBuffer::<128, 64, 1024, BinaryColor>::new();
+    /// let mut mirror = PackedBuffer::<128, 64, 1024, BinaryColor>::new();
+    ///
+    /// device
+    ///     .fill_solid(&Rectangle::new(Point::new(10, 10), Size::new(50, 30)), BinaryColor::On)
+    ///     .unwrap();
+    ///
+    /// let mut buf = [0u8; 64];
+    /// let mut sink = SliceSink::new(&mut buf);
+    /// device.write_rle(&mut sink).unwrap();
+    /// device.clear_active_area();
+    ///
+    /// mirror.apply_rle(sink.written()).unwrap();
+    ///
+    /// assert!(mirror.diff(&device).is_empty());
+    /// ```
+    pub fn write_rle<S: ByteSink>(&self, sink: &mut S) -> Result<(), S::Error> {
+        self.write_rle_area(&self.active_area(), sink)
+    }
+
+    /// Encode the blocks covering the given area as run-length encoded spans.
+    pub(crate) fn write_rle_area<S: ByteSink>(
+        &self,
+        area: &Rectangle,
+        sink: &mut S,
+    ) -> Result<(), S::Error> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::FITS_U16;
+
+        let column = area.intersection(&self.area).top_left.x as u16;
+
+        let blocks = self.blocks(area);
+        let first_page = blocks.buffer_idx as u32 / W;
+
+        for (page, block) in (first_page..).zip(blocks) {
+            for value in [page as u16, column, block.len() as u16] {
+                sink.write_bytes(&value.to_le_bytes())?;
+            }
+
+            encode(block, sink)?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply run-length encoded spans written by [`write_rle`](Self::write_rle).
+    ///
+    /// Bytes are copied into the buffer directly, ignoring the raster operation, and the active
+    /// area is extended to cover each span. If an error is returned, any spans before the invalid
+    /// one have already been applied.
+    pub fn apply_rle(&mut self, stream: &[u8]) -> Result<(), RleError> {
+        let active_area = &mut self.active_area;
+
+        apply_spans(
+            &mut self.buf,
+            Size::new(W, H),
+            Self::SLOT_BITS,
+            &self.area,
+            stream,
+            |area| active_area.update_from_rect(area),
+        )
+    }
+}
+
+/// Decode spans into a buffer with the vertical layout.
+///
+/// Each span must lie within the columns of `area` and in a page that overlaps its rows, otherwise
+/// [`RleError::OutOfBounds`] is returned before the span is decoded. `area` must be inside the
+/// buffer. `on_span` is called with the pixels covered by each span after it has been applied.
+pub(crate) fn apply_spans(
+    buf: &mut [u8],
+    size: Size,
+    slot: u32,
+    area: &Rectangle,
+    mut stream: &[u8],
+    mut on_span: impl FnMut(Rectangle),
+) -> Result<(), RleError> {
+    let pixels_per_page = u8::BITS / slot;
+
+    let left = area.top_left.x as u32;
+    let right = left + area.size.width;
+    let top = area.top_left.y as u32;
+    let bottom = top + area.size.height;
+
+    while !stream.is_empty() {
+        let header = stream.get(..SPAN_HEADER_LEN).ok_or(RleError::Truncated)?;
+
+        let [page, column, length] =
+            [0, 2, 4].map(|offset| u16::from_le_bytes([header[offset], header[offset + 1]]));
+
+        let (x, len) = (u32::from(column), u32::from(length));
+
+        // Rows of the span's page, which may extend past the bottom of the area or buffer
+        let page_top = u32::from(page) * pixels_per_page;
+        let page_bottom = page_top + pixels_per_page;
+
+        if x < left || x + len > right || page_top >= bottom || page_bottom <= top {
+            return Err(RleError::OutOfBounds {
+                page,
+                column,
+                length,
+            });
+        }
+
+        let start = (u32::from(page) * size.width + x) as usize;
+        let used = decode(
+            &stream[SPAN_HEADER_LEN..],
+            &mut buf[start..start + len as usize],
+        )?;
+
+        stream = &stream[SPAN_HEADER_LEN + used..];
+
+        let height = pixels_per_page.min(size.height - page_top);
+
+        on_span(Rectangle::new(
+            Point::new(x as i32, page_top as i32),
+            Size::new(len, height),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer_size, SliceSink};
+    use embedded_graphics::{
+        pixelcolor::{BinaryColor, Gray2},
+        prelude::*,
+    };
+    use proptest::prelude::*;
+
+    fn encoded(data: &[u8]) -> Vec<u8> {
+        let mut buf = [0u8; 1024];
+        let mut sink = SliceSink::new(&mut buf);
+
+        encode(data, &mut sink).unwrap();
+
+        sink.written().to_vec()
+    }
+
+    #[test]
+    fn packets() {
+        assert_eq!(encoded(&[]), [0u8; 0]);
+        assert_eq!(encoded(&[7]), [0, 7]);
+        assert_eq!(encoded(&[7, 7]), [255, 7]);
+        assert_eq!(encoded(&[1, 2, 3, 3, 3]), [1, 1, 2, 254, 3]);
+        // A pair inside a literal isn't worth a packet of its own
+        assert_eq!(encoded(&[1, 2, 2, 3]), [3, 1, 2, 2, 3]);
+        assert_eq!(encoded(&[0; 300]), [129, 0, 129, 0, 213, 0]);
+
+        let literal: Vec<u8> = (0..=200).collect();
+        let packed = encoded(&literal);
+        assert_eq!(packed.len(), literal.len() + 2);
+        assert_eq!(packed[0], 127);
+        assert_eq!(packed[129], 72);
+
+        // No-op control bytes are skipped
+        let mut out = [0u8; 2];
+        assert_eq!(decode(&[128, 1, 4, 5], &mut out), Ok(4));
+        assert_eq!(out, [4, 5]);
+    }
+
+    #[test]
+    fn decode_errors() {
+        let mut out = [0u8; 4];
+
+        assert_eq!(decode(&[], &mut out), Err(RleError::Truncated));
+        assert_eq!(decode(&[3, 1, 2], &mut out), Err(RleError::Truncated));
+        assert_eq!(decode(&[250], &mut out), Err(RleError::Truncated));
+        assert_eq!(
+            decode(&[4, 1, 2, 3, 4, 5], &mut out),
+            Err(RleError::Overrun)
+        );
+        assert_eq!(decode(&[251, 1], &mut out), Err(RleError::Overrun));
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(
+            data in prop::collection::vec(prop_oneof![Just(0u8), Just(0xff), any::<u8>()], 0..600)
+        ) {
+            let packed = encoded(&data);
+
+            let mut out = vec![0u8; data.len()];
+            prop_assert_eq!(decode(&packed, &mut out), Ok(packed.len()));
+            prop_assert_eq!(out, data.clone());
+
+            prop_assert!(packed.len() <= data.len() + data.len().div_ceil(MAX_PACKET));
+        }
+    }
+
+    type Display = PackedBuffer<40, 21, { buffer_size::<Gray2>(40, 21) }, Gray2>;
+
+    fn mirror(device: &Display, receiver: &mut Display) -> usize {
+        let mut buf = [0u8; 512];
+        let mut sink = SliceSink::new(&mut buf);
+
+        device.write_rle(&mut sink).unwrap();
+
+        let len = sink.written().len();
+        receiver.apply_rle(sink.written()).unwrap();
+
+        len
+    }
+
+    #[test]
+    fn mirror_active_blocks() {
+        let mut device = Display::new();
+        let mut receiver = Display::new();
+
+        // Nothing to send
+        assert_eq!(mirror(&device, &mut receiver), 0);
+
+        let area = Rectangle::new(Point::new(5, 3), Size::new(30, 15));
+        device.fill_solid(&area, Gray2::new(2)).unwrap();
+        device.set_pixel(Point::new(39, 20), Gray2::new(1));
+
+        let raw_len: usize = device.active_blocks().map(<[u8]>::len).sum();
+        let len = mirror(&device, &mut receiver);
+        device.clear_active_area();
+
+        assert!(
+            receiver.diff(&device).is_empty(),
+            "{}",
+            receiver.diff(&device)
+        );
+        assert!(len < raw_len / 2, "{} {}", len, raw_len);
+        assert_eq!(
+            receiver.active_area(),
+            Rectangle::with_corners(Point::new(5, 0), Point::new(39, 20))
+        );
+
+        // Later updates only send their own blocks
+        receiver.clear_active_area();
+
+        let area = Rectangle::new(Point::new(10, 6), Size::new(3, 2));
+        device.fill_solid(&area, Gray2::new(3)).unwrap();
+
+        mirror(&device, &mut receiver);
+
+        assert!(
+            receiver.diff(&device).is_empty(),
+            "{}",
+            receiver.diff(&device)
+        );
+        assert_eq!(
+            receiver.active_area(),
+            Rectangle::new(Point::new(10, 4), Size::new(3, 4))
+        );
+    }
+
+    #[test]
+    fn apply_errors() {
+        let mut display = PackedBuffer::<16, 12, 32, BinaryColor>::new();
+
+        // Page 2 is past the end of a 12 pixel high display
+        assert_eq!(
+            display.apply_rle(&[2, 0, 0, 0, 1, 0, 0, 0xff]),
+            Err(RleError::OutOfBounds {
+                page: 2,
+                column: 0,
+                length: 1
+            })
+        );
+        assert_eq!(
+            display.apply_rle(&[1, 0, 10, 0, 7, 0, 250, 0xff]),
+            Err(RleError::OutOfBounds {
+                page: 1,
+                column: 10,
+                length: 7
+            })
+        );
+        assert_eq!(display.apply_rle(&[1, 0, 10]), Err(RleError::Truncated));
+        assert_eq!(
+            display.apply_rle(&[1, 0, 10, 0, 6, 0, 250]),
+            Err(RleError::Truncated)
+        );
+
+        display.apply_rle(&[1, 0, 10, 0, 6, 0, 251, 0xff]).unwrap();
+
+        assert_eq!(
+            display.active_area(),
+            Rectangle::new(Point::new(10, 8), Size::new(6, 4))
+        );
+        assert_eq!(display.get_pixel(Point::new(15, 11)), Some(BinaryColor::On));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{blit::column_byte, mask, raster_op::RasterOp, PackedBuffer};
+use embedded_graphics_core::{
+    geometry::{Point, Size},
+    pixelcolor::{IntoStorage, PixelColor},
+    primitives::Rectangle,
+};
+
+impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    /// Shift the entire buffer contents by `dx` pixels to the right and `dy` pixels down.
+    ///
+    /// Negative values shift left or up. Pixels shifted off the display are discarded and the
+    /// vacated area is filled with `fill`.
+    ///
+    /// See [`scroll_region`](Self::scroll_region) for details.
+    pub fn scroll(&mut self, dx: i32, dy: i32, fill: C) {
+        let area = self.area;
+
+        self.scroll_region(&area, dx, dy, fill);
+    }
+
+    /// Shift the contents of a region of the buffer by `dx` pixels to the right and `dy` pixels
+    /// down.
+    ///
+    /// The region is clipped to the display dimensions. Pixels shifted outside the region are
+    /// discarded and the vacated part of the region is filled with `fill`. Pixels outside the
+    /// region are left untouched, and the active area is updated to cover the whole region.
+    ///
+    /// Vertical shifts move bits down each column with carries between pages, and horizontal
+    /// shifts move whole block rows with `copy_within`. The current raster operation is ignored.
+    pub fn scroll_region(&mut self, region: &Rectangle, dx: i32, dy: i32, fill: C) {
+        let region = region.intersection(&self.area);
+
+        if region.is_zero_sized() || (dx == 0 && dy == 0) {
+            return;
+        }
+
+        let Size { width, height } = region.size;
+
+        if dx.unsigned_abs() >= width || dy.unsigned_abs() >= height {
+            self.fill_rect_replace(&region, fill);
+        } else {
+            self.shift_horizontal(&region, dx);
+            self.shift_vertical(&region, dy);
+
+            // Fill vacated columns, then vacated rows
+            let x = if dx > 0 {
+                region.top_left.x
+            } else {
+                region.top_left.x + (width as i32 + dx)
+            };
+            let y = if dy > 0 {
+                region.top_left.y
+            } else {
+                region.top_left.y + (height as i32 + dy)
+            };
+
+            self.fill_rect_replace(
+                &Rectangle::new(
+                    Point::new(x, region.top_left.y),
+                    Size::new(dx.unsigned_abs(), height),
+                ),
+                fill,
+            );
+            self.fill_rect_replace(
+                &Rectangle::new(
+                    Point::new(region.top_left.x, y),
+                    Size::new(width, dy.unsigned_abs()),
+                ),
+                fill,
+            );
+        }
+
+        self.active_area.update_from_rect(region);
+    }
+
+    /// Fill an area, ignoring the current raster operation.
+    fn fill_rect_replace(&mut self, rect: &Rectangle, color: C) {
+        let pattern = mask::repeat(color.into_storage(), Self::SLOT_BITS);
+
+        self.fill_pattern(rect, pattern, RasterOp::Replace);
+    }
+
+    /// The start and end bit offsets within each column of the rows covered by the region.
+    fn region_bits(region: &Rectangle) -> (u32, u32) {
+        let bit_start = region.top_left.y as u32 * Self::SLOT_BITS;
+        let bit_end = bit_start + region.size.height * Self::SLOT_BITS;
+
+        (bit_start, bit_end)
+    }
+
+    /// Move block rows in the region `dx` pixels to the right.
+    ///
+    /// `dx` must be less than the region width.
+    fn shift_horizontal(&mut self, region: &Rectangle, dx: i32) {
+        if dx == 0 {
+            return;
+        }
+
+        let (bit_start, bit_end) = Self::region_bits(region);
+
+        let width = region.size.width as usize;
+        let distance = dx.unsigned_abs() as usize;
+
+        for page in bit_start / u8::BITS..bit_end.div_ceil(u8::BITS) {
+            let mask = mask::page_mask(page * u8::BITS, bit_start, bit_end);
+
+            let row = self.block_range(page as usize, region);
+
+            // Whole bytes can be moved, otherwise bits outside the region need preserving
+            if mask == u8::MAX {
+                if dx > 0 {
+                    row.copy_within(0..width - distance, distance);
+                } else {
+                    row.copy_within(distance..width, 0);
+                }
+            } else if dx > 0 {
+                for idx in (distance..width).rev() {
+                    row[idx] = RasterOp::Replace.apply(row[idx], row[idx - distance], mask);
+                }
+            } else {
+                for idx in 0..width - distance {
+                    row[idx] = RasterOp::Replace.apply(row[idx], row[idx + distance], mask);
+                }
+            }
+        }
+    }
+
+    /// Move the columns in the region `dy` pixels down.
+    ///
+    /// `dy` must be less than the region height.
+    fn shift_vertical(&mut self, region: &Rectangle, dy: i32) {
+        if dy == 0 {
+            return;
+        }
+
+        let (bit_start, bit_end) = Self::region_bits(region);
+
+        let shift = dy * Self::SLOT_BITS as i32;
+
+        // Destination bits that receive data from inside the region
+        let (dest_start, dest_end) = if dy > 0 {
+            (bit_start + shift as u32, bit_end)
+        } else {
+            (bit_start, bit_end - shift.unsigned_abs())
+        };
+
+        let pages = dest_start / u8::BITS..dest_end.div_ceil(u8::BITS);
+
+        let mut shift_page = |page: u32| {
+            let page_start = page * u8::BITS;
+            let mask = mask::page_mask(page_start, dest_start, dest_end);
+
+            for x in region.columns() {
+                let src = column_byte(&self.buf, W, x as u32, page_start as i32 - shift);
+
+                let idx = (page * W) as usize + x as usize;
+
+                self.buf[idx] = RasterOp::Replace.apply(self.buf[idx], src, mask);
+            }
+        };
+
+        // Shifting down reads from pages above the destination, so start from the bottom to avoid
+        // overwriting source data before it's read, and vice versa.
+        if dy > 0 {
+            pages.rev().for_each(&mut shift_page);
+        } else {
+            pages.for_each(&mut shift_page);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{buffer_size, PackedBuffer, RasterOp};
+    use embedded_graphics::pixelcolor::{BinaryColor, Gray2};
+    use embedded_graphics_core::{
+        geometry::{Dimensions, Point, Size},
+        pixelcolor::{IntoStorage, PixelColor},
+        primitives::{PointsIter, Rectangle},
+        Pixel,
+    };
+
+    fn pattern<const W: u32, const H: u32, const N: usize, C>(
+        display: &mut PackedBuffer<W, H, N, C>,
+        colors: &[C],
+    ) where
+        C: PixelColor + IntoStorage<Storage = u8>,
+    {
+        for point in display.bounding_box().points() {
+            let idx = (point.x * 5 + point.y * 3 + point.x * point.y) as usize % colors.len();
+            display.set_pixel(point, colors[idx]);
+        }
+
+        display.clear_active_area();
+    }
+
+    fn check<const W: u32, const H: u32, const N: usize, C>(
+        before: &PackedBuffer<W, H, N, C>,
+        after: &PackedBuffer<W, H, N, C>,
+        region: &Rectangle,
+        dx: i32,
+        dy: i32,
+        fill: C,
+    ) where
+        C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw> + core::fmt::Debug,
+    {
+        let region = region.intersection(&before.bounding_box());
+
+        for Pixel(point, color) in after.pixels() {
+            let expected = if !region.contains(point) {
+                before.get_pixel(point).unwrap()
+            } else if region.contains(point - Point::new(dx, dy)) {
+                before.get_pixel(point - Point::new(dx, dy)).unwrap()
+            } else {
+                fill
+            };
+
+            assert_eq!(
+                color, expected,
+                "{:?} in {:?} by ({}, {})",
+                point, region, dx, dy
+            );
+        }
+    }
+
+    const OFFSETS: &[(i32, i32)] = &[
+        (0, 1),
+        (0, -1),
+        (1, 0),
+        (-1, 0),
+        (3, 5),
+        (-7, 9),
+        (11, -13),
+        (-2, -17),
+        (0, 8),
+        (0, -16),
+        (40, 0),
+        (0, -30),
+    ];
+
+    #[test]
+    fn scroll_whole_display() {
+        type Display = PackedBuffer<20, 30, { buffer_size::<BinaryColor>(20, 30) }, BinaryColor>;
+
+        let mut before = Display::new();
+        pattern(&mut before, &[BinaryColor::On, BinaryColor::Off]);
+
+        for &(dx, dy) in OFFSETS {
+            for fill in [BinaryColor::On, BinaryColor::Off] {
+                let mut after = Display::new();
+                after.blit(before.as_slice(), &before.bounding_box(), Point::zero());
+                after.clear_active_area();
+
+                after.scroll(dx, dy, fill);
+
+                check(&before, &after, &before.bounding_box(), dx, dy, fill);
+                assert_eq!(after.active_area(), before.bounding_box());
+            }
+        }
+    }
+
+    #[test]
+    fn scroll_region() {
+        type Display = PackedBuffer<24, 30, { buffer_size::<Gray2>(24, 30) }, Gray2>;
+
+        let regions = [
+            Rectangle::new(Point::new(3, 5), Size::new(15, 20)),
+            Rectangle::new(Point::new(0, 8), Size::new(24, 8)),
+            Rectangle::new(Point::new(-4, 21), Size::new(12, 50)),
+        ];
+
+        let mut before = Display::new();
+        pattern(
+            &mut before,
+            &[Gray2::new(0), Gray2::new(1), Gray2::new(2), Gray2::new(3)],
+        );
+
+        for region in &regions {
+            for &(dx, dy) in OFFSETS {
+                let mut after = Display::new();
+                after.blit(before.as_slice(), &before.bounding_box(), Point::zero());
+                after.clear_active_area();
+
+                // Scrolling is unaffected by the raster op
+                after.set_raster_op(RasterOp::Xor);
+
+                after.scroll_region(region, dx, dy, Gray2::new(2));
+
+                check(&before, &after, region, dx, dy, Gray2::new(2));
+                assert_eq!(
+                    after.active_area(),
+                    region.intersection(&before.bounding_box())
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Copy buffer contents into an `embedded-graphics-simulator` display.
+//!
+//! This lets UI code that draws into a [`PackedBuffer`] run unchanged on a desktop, with the
+//! simulator window showing exactly what the framebuffer holds. The simulator's [`OutputImage`]
+//! can also be used to render the buffer to a PNG without opening a window, e.g. in CI.
+//!
+//! ```rust
+//! use embedded_graphics::{
+//!     pixelcolor::BinaryColor,
+//!     prelude::*,
+//!     primitives::{Circle, PrimitiveStyle},
+//! };
+//! use embedded_graphics_simulator::{OutputSettings, SimulatorDisplay};
+//! use packed_display_buffer::{
+//!     simulator::{Rotation, SimulatorBridge},
+//!     PackedBuffer,
+//! };
+//!
+//! let mut framebuffer = PackedBuffer::<128, 64, 1024, BinaryColor>::new();
+//!
+//! Circle::new(Point::new(10, 10), 20)
+//!     .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+//!     .draw(&mut framebuffer)
+//!     .unwrap();
+//!
+//! let bridge = SimulatorBridge::new().rotation(Rotation::Rotate90);
+//! let mut display: SimulatorDisplay<BinaryColor> = bridge.display(&framebuffer);
+//!
+//! bridge.update(&framebuffer, &mut display);
+//! framebuffer.clear_active_area();
+//!
+//! let image = display.to_rgb_output_image(&OutputSettings::default());
+//! assert_eq!(image.as_image_buffer().dimensions(), (64, 128));
+//! ```
+//!
+//! [`OutputImage`]: embedded_graphics_simulator::OutputImage
+
+use crate::{pixels::from_storage, PackedBuffer};
+use core::{fmt, marker::PhantomData};
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, Point, Size},
+    pixelcolor::{IntoStorage, PixelColor},
+    primitives::{PointsIter, Rectangle},
+    Pixel,
+};
+use embedded_graphics_simulator::SimulatorDisplay;
+
+/// Clockwise rotation applied when copying a buffer into a simulator display.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Rotation {
+    /// No rotation.
+    #[default]
+    Rotate0,
+
+    /// Rotate by 90 degrees clockwise, so the top of the buffer is on the right.
+    Rotate90,
+
+    /// Rotate by 180 degrees.
+    Rotate180,
+
+    /// Rotate by 270 degrees clockwise, so the top of the buffer is on the left.
+    Rotate270,
+}
+
+impl Rotation {
+    /// Size of a buffer of the given size after rotation.
+    pub fn rotate_size(self, size: Size) -> Size {
+        match self {
+            Rotation::Rotate0 | Rotation::Rotate180 => size,
+            Rotation::Rotate90 | Rotation::Rotate270 => Size::new(size.height, size.width),
+        }
+    }
+
+    /// Position of a point in a buffer of the given size after rotation.
+    pub fn rotate_point(self, point: Point, size: Size) -> Point {
+        let right = size.width as i32 - 1;
+        let bottom = size.height as i32 - 1;
+
+        match self {
+            Rotation::Rotate0 => point,
+            Rotation::Rotate90 => Point::new(bottom - point.y, point.x),
+            Rotation::Rotate180 => Point::new(right - point.x, bottom - point.y),
+            Rotation::Rotate270 => Point::new(point.y, right - point.x),
+        }
+    }
+}
+
+/// Copies the contents of a [`PackedBuffer`] with colors `C` into a [`SimulatorDisplay`] with
+/// colors `D`, converting each color with `F`.
+///
+/// Pixels are read by position, so the buffer's page layout doesn't affect the copy and there is
+/// no layout to configure; only the rotation and color mapping change what is shown.
+///
+/// See the [module documentation](self) for an example.
+pub struct SimulatorBridge<C, D, F = fn(C) -> D> {
+    rotation: Rotation,
+    color_map: F,
+    colors: PhantomData<fn(C) -> D>,
+}
+
+impl<C, D> SimulatorBridge<C, D>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw> + Into<D>,
+    D: PixelColor,
+{
+    /// Create a bridge that converts colors with [`Into`].
+    pub fn new() -> Self {
+        Self::with_color_map(Into::into)
+    }
+}
+
+impl<C, D> Default for SimulatorBridge<C, D>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw> + Into<D>,
+    D: PixelColor,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C, D, F> SimulatorBridge<C, D, F>
+where
+    C: PixelColor + IntoStorage<Storage = u8> + From<C::Raw>,
+    D: PixelColor,
+    F: Fn(C) -> D,
+{
+    /// Create a bridge that converts colors with the given function or closure.
+    ///
+    /// This can be used to show a 2bpp buffer in the colors of a tri-color e-paper panel, for
+    /// example.
+    pub fn with_color_map(color_map: F) -> Self {
+        Self {
+            rotation: Rotation::Rotate0,
+            color_map,
+            colors: PhantomData,
+        }
+    }
+
+    /// Set the rotation applied when copying pixels.
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+
+        self
+    }
+
+    /// Create a simulator display with the rotated size of the buffer.
+    ///
+    /// The display is filled with the mapped color of a zeroed pixel.
+    pub fn display<const W: u32, const H: u32, const N: usize>(
+        &self,
+        buffer: &PackedBuffer<W, H, N, C>,
+    ) -> SimulatorDisplay<D> {
+        SimulatorDisplay::with_default_color(
+            self.rotation.rotate_size(buffer.bounding_box().size),
+            (self.color_map)(from_storage(0)),
+        )
+    }
+
+    /// Copy the whole buffer into the display.
+    pub fn update<const W: u32, const H: u32, const N: usize>(
+        &self,
+        buffer: &PackedBuffer<W, H, N, C>,
+        display: &mut SimulatorDisplay<D>,
+    ) {
+        self.update_area(buffer, &buffer.bounding_box(), display)
+    }
+
+    /// Copy only the active area of the buffer into the display.
+    ///
+    /// This mirrors what a driver would send to the hardware after each frame. Clear the active
+    /// area with [`PackedBuffer::clear_active_area`] once the display has been updated.
+    pub fn update_active_area<const W: u32, const H: u32, const N: usize>(
+        &self,
+        buffer: &PackedBuffer<W, H, N, C>,
+        display: &mut SimulatorDisplay<D>,
+    ) {
+        self.update_area(buffer, &buffer.active_area(), display)
+    }
+
+    /// Copy the given area of the buffer into the display.
+    ///
+    /// The area is in buffer coordinates, before rotation, and is clipped to the buffer.
+    pub fn update_area<const W: u32, const H: u32, const N: usize>(
+        &self,
+        buffer: &PackedBuffer<W, H, N, C>,
+        area: &Rectangle,
+        display: &mut SimulatorDisplay<D>,
+    ) {
+        let size = Size::new(W, H);
+        let area = area.intersection(&buffer.bounding_box());
+
+        let pixels = area.points().map(|point| {
+            Pixel(
+                self.rotation.rotate_point(point, size),
+                (self.color_map)(buffer.get_pixel_unchecked(point)),
+            )
+        });
+
+        // Drawing into a simulator display can't fail
+        let _ = display.draw_iter(pixels);
+    }
+}
+
+impl<C, D, F> fmt::Debug for SimulatorBridge<C, D, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimulatorBridge")
+            .field("rotation", &self.rotation)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C, D, F: Clone> Clone for SimulatorBridge<C, D, F> {
+    fn clone(&self) -> Self {
+        Self {
+            rotation: self.rotation,
+            color_map: self.color_map.clone(),
+            colors: PhantomData,
+        }
+    }
+}
+
+impl<C, D, F: Copy> Copy for SimulatorBridge<C, D, F> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_size;
+    use embedded_graphics::pixelcolor::{BinaryColor, Gray2, Rgb888, RgbColor};
+    use embedded_graphics_simulator::{OutputSettingsBuilder, SimulatorDisplay};
+
+    type Display = PackedBuffer<5, 3, 5, BinaryColor>;
+
+    fn display() -> Display {
+        Display::from_art(
+            "
+            ##...
+            .....
+            ....#
+            ",
+        )
+        .unwrap()
+    }
+
+    fn lit(display: &SimulatorDisplay<BinaryColor>) -> Vec<Point> {
+        display
+            .bounding_box()
+            .points()
+            .filter(|point| display.get_pixel(*point) == BinaryColor::On)
+            .collect()
+    }
+
+    #[test]
+    fn rotations() {
+        let buffer = display();
+
+        let cases = [
+            (Rotation::Rotate0, Size::new(5, 3), [(0, 0), (1, 0), (4, 2)]),
+            (
+                Rotation::Rotate90,
+                Size::new(3, 5),
+                [(2, 0), (2, 1), (0, 4)],
+            ),
+            (
+                Rotation::Rotate180,
+                Size::new(5, 3),
+                [(0, 0), (3, 2), (4, 2)],
+            ),
+            (
+                Rotation::Rotate270,
+                Size::new(3, 5),
+                [(2, 0), (0, 3), (0, 4)],
+            ),
+        ];
+
+        for (rotation, size, expected) in cases {
+            let bridge = SimulatorBridge::new().rotation(rotation);
+
+            let mut display = bridge.display(&buffer);
+            bridge.update(&buffer, &mut display);
+
+            assert_eq!(display.bounding_box().size, size, "{:?}", rotation);
+
+            let mut expected = expected.map(|(x, y)| Point::new(x, y)).to_vec();
+            expected.sort_by_key(|p| (p.y, p.x));
+
+            assert_eq!(lit(&display), expected, "{:?}", rotation);
+        }
+    }
+
+    #[test]
+    fn active_area_only() {
+        let mut buffer = display();
+        let bridge = SimulatorBridge::new();
+
+        let mut display = bridge.display(&buffer);
+
+        // Nothing has been drawn since the buffer was created
+        bridge.update_active_area(&buffer, &mut display);
+        assert_eq!(lit(&display), []);
+
+        buffer.set_pixel(Point::new(2, 1), BinaryColor::On);
+        bridge.update_active_area(&buffer, &mut display);
+
+        assert_eq!(lit(&display), [Point::new(2, 1)]);
+    }
+
+    #[test]
+    fn png_output() {
+        let buffer = PackedBuffer::<4, 2, { buffer_size::<Gray2>(4, 2) }, Gray2>::from_art(
+            "
+            0123
+            3210
+            ",
+        )
+        .unwrap();
+
+        // A capturing closure, e.g. a palette chosen at runtime
+        let palette = [Rgb888::WHITE, Rgb888::BLACK, Rgb888::RED, Rgb888::YELLOW];
+
+        let bridge = SimulatorBridge::with_color_map(move |color: Gray2| {
+            palette[usize::from(color.into_storage())]
+        });
+
+        let mut display = bridge.display(&buffer);
+        bridge.update(&buffer, &mut display);
+
+        let settings = OutputSettingsBuilder::new().scale(2).build();
+        let image = display.to_rgb_output_image(&settings);
+
+        assert_eq!(image.as_image_buffer().dimensions(), (8, 4));
+        assert_eq!(image.as_image_buffer().get_pixel(5, 0).0, [255, 0, 0]);
+        assert_eq!(image.as_image_buffer().get_pixel(1, 3).0, [255, 255, 0]);
+
+        let path = std::env::temp_dir().join("packed-display-buffer-simulator.png");
+        image.save_png(&path).unwrap();
+
+        let loaded = SimulatorDisplay::<Rgb888>::load_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.get_pixel(Point::new(4, 2)), Rgb888::BLACK);
+        assert_eq!(loaded.get_pixel(Point::new(0, 0)), Rgb888::WHITE);
+    }
+}
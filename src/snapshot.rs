@@ -0,0 +1,598 @@
+//! A compact binary format for saving and restoring buffer state.
+//!
+//! Snapshots are intended for devices that keep the screen contents across a deep sleep, e.g.
+//! e-paper displays that only need a partial refresh after waking. All values are little endian:
+//!
+//! | Offset | Size | Contents                                                   |
+//! |--------|------|------------------------------------------------------------|
+//! | 0      | 3    | Magic bytes, `PDB`                                         |
+//! | 3      | 1    | Format version, currently `1`                              |
+//! | 4      | 1    | Bits per pixel                                             |
+//! | 5      | 1    | Layout, `0` for [`Layout::Vertical`], `1` for horizontal   |
+//! | 6      | 8    | Width and height as `u32`s                                 |
+//! | 14     | 16   | Active area x, y, width and height as `u32`s               |
+//! | 30     | 4    | Length of the pixel data as a `u32`                        |
+//! | 34     | N    | Pixel data                                                 |
+//!
+//! An active area with a zero width or height means nothing has been drawn since it was cleared.
+
+use crate::{byte_direction::Layout, pnm::ByteSink, PackedBuffer};
+use core::fmt;
+use embedded_graphics_core::{
+    geometry::{Point, Size},
+    pixelcolor::{raw::RawData, IntoStorage, PixelColor},
+    primitives::Rectangle,
+};
+
+const MAGIC: &[u8; 3] = b"PDB";
+const VERSION: u8 = 1;
+
+/// Length of the snapshot header before the pixel data.
+const HEADER_LEN: usize = 34;
+
+/// Error returned when a snapshot can't be restored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The data isn't a snapshot, or the header is truncated.
+    InvalidHeader,
+
+    /// The snapshot was written by a newer, incompatible version of this crate.
+    UnsupportedVersion(u8),
+
+    /// The snapshot dimensions don't match the buffer dimensions.
+    SizeMismatch {
+        /// Width of the snapshot.
+        width: u32,
+
+        /// Height of the snapshot.
+        height: u32,
+    },
+
+    /// The snapshot has a different color depth to the buffer.
+    DepthMismatch(u8),
+
+    /// The snapshot pixel data is stored in a different layout to the buffer.
+    LayoutMismatch(Layout),
+
+    /// The amount of pixel data doesn't match the buffer length.
+    LengthMismatch(usize),
+
+    /// The active area extends outside the display.
+    InvalidActiveArea,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::InvalidHeader => f.write_str("invalid snapshot header"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::SizeMismatch { width, height } => {
+                write!(f, "snapshot is {}x{} pixels", width, height)
+            }
+            SnapshotError::DepthMismatch(bpp) => write!(f, "snapshot has {}bpp pixels", bpp),
+            SnapshotError::LayoutMismatch(layout) => {
+                write!(f, "snapshot has {:?} layout", layout)
+            }
+            SnapshotError::LengthMismatch(len) => {
+                write!(f, "snapshot has {} bytes of pixel data", len)
+            }
+            SnapshotError::InvalidActiveArea => {
+                f.write_str("snapshot active area is outside the display")
+            }
+        }
+    }
+}
+
+/// Buffer properties stored alongside the pixel data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Header {
+    bpp: u8,
+    layout: Layout,
+    width: u32,
+    height: u32,
+    active_area: [u32; 4],
+}
+
+impl Header {
+    fn new<const W: u32, const H: u32, const N: usize, C>(buffer: &PackedBuffer<W, H, N, C>) -> Self
+    where
+        C: PixelColor + IntoStorage<Storage = u8>,
+    {
+        let Rectangle {
+            top_left: Point { x, y },
+            size: Size { width, height },
+        } = buffer.active_area();
+
+        Self {
+            bpp: C::Raw::BITS_PER_PIXEL as u8,
+            layout: Layout::Vertical,
+            width: W,
+            height: H,
+            active_area: [x as u32, y as u32, width, height],
+        }
+    }
+
+    /// Check the header describes a buffer of the given type, returning the active area.
+    fn validate<const W: u32, const H: u32, const N: usize, C>(
+        &self,
+    ) -> Result<Rectangle, SnapshotError>
+    where
+        C: PixelColor + IntoStorage<Storage = u8>,
+    {
+        if (self.width, self.height) != (W, H) {
+            return Err(SnapshotError::SizeMismatch {
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        if usize::from(self.bpp) != C::Raw::BITS_PER_PIXEL {
+            return Err(SnapshotError::DepthMismatch(self.bpp));
+        }
+
+        if self.layout != Layout::Vertical {
+            return Err(SnapshotError::LayoutMismatch(self.layout));
+        }
+
+        let [x, y, width, height] = self.active_area;
+
+        let in_bounds = x.checked_add(width).is_some_and(|right| right <= W)
+            && y.checked_add(height).is_some_and(|bottom| bottom <= H);
+
+        if !in_bounds {
+            return Err(SnapshotError::InvalidActiveArea);
+        }
+
+        Ok(Rectangle::new(
+            Point::new(x as i32, y as i32),
+            Size::new(width, height),
+        ))
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut word = [0u8; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+
+    u32::from_le_bytes(word)
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    /// Length of a snapshot of this buffer in bytes.
+    ///
+    /// This can be used to size a [`SliceSink`](crate::SliceSink) for
+    /// [`write_snapshot`](Self::write_snapshot).
+    pub const SNAPSHOT_LEN: usize = HEADER_LEN + N;
+
+    /// Write the buffer contents, dimensions, color depth and active area as a binary snapshot.
+    ///
+    /// See the [`snapshot`](crate::snapshot) module for details of the format. The raster
+    /// operation isn't saved.
+    pub fn write_snapshot<S: ByteSink>(&self, sink: &mut S) -> Result<(), S::Error> {
+        let header = Header::new(self);
+
+        sink.write_bytes(MAGIC)?;
+        sink.write_bytes(&[VERSION, header.bpp, header.layout as u8])?;
+
+        for value in [header.width, header.height]
+            .iter()
+            .chain(&header.active_area)
+            .chain(&[N as u32])
+        {
+            sink.write_bytes(&value.to_le_bytes())?;
+        }
+
+        sink.write_bytes(&self.buf)
+    }
+
+    /// Restore the buffer contents and active area from a snapshot.
+    ///
+    /// The snapshot must have been taken from a buffer with the same dimensions and color depth.
+    /// The buffer is left unchanged if an error is returned.
+    pub fn restore_snapshot(&mut self, snapshot: &[u8]) -> Result<(), SnapshotError> {
+        let header = snapshot
+            .get(..HEADER_LEN)
+            .filter(|header| header.starts_with(MAGIC))
+            .ok_or(SnapshotError::InvalidHeader)?;
+
+        if header[3] != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header[3]));
+        }
+
+        let layout = match header[5] {
+            0 => Layout::Vertical,
+            1 => Layout::Horizontal,
+            _ => return Err(SnapshotError::InvalidHeader),
+        };
+
+        let header_fields = Header {
+            bpp: header[4],
+            layout,
+            width: read_u32(header, 6),
+            height: read_u32(header, 10),
+            active_area: [
+                read_u32(header, 14),
+                read_u32(header, 18),
+                read_u32(header, 22),
+                read_u32(header, 26),
+            ],
+        };
+
+        let active_area = header_fields.validate::<W, H, N, C>()?;
+
+        let len = read_u32(header, 30) as usize;
+        let data = &snapshot[HEADER_LEN..];
+
+        if len != N {
+            return Err(SnapshotError::LengthMismatch(len));
+        }
+
+        if data.len() != N {
+            return Err(SnapshotError::LengthMismatch(data.len()));
+        }
+
+        self.buf.copy_from_slice(data);
+        self.restore_active_area(active_area);
+
+        Ok(())
+    }
+
+    /// Create a buffer from a snapshot.
+    ///
+    /// See [`restore_snapshot`](Self::restore_snapshot) for details.
+    ///
+    /// ```rust
+    /// use embedded_graphics_core::{pixelcolor::BinaryColor, prelude::*};
+    /// use packed_display_buffer::{PackedBuffer, SliceSink};
+    ///
+    /// type Display = PackedBuffer<128, 64, 1024, BinaryColor>;
+    ///
+    /// let mut display = Display::new();
+    /// display.set_pixel(Point::new(10, 20), BinaryColor::On);
+    ///
+    /// let mut buf = [0u8; Display::SNAPSHOT_LEN];
+    /// let mut sink = SliceSink::new(&mut buf);
+    /// display.write_snapshot(&mut sink).unwrap();
+    ///
+    /// // ...deep sleep...
+    ///
+    /// let restored = Display::from_snapshot(&buf).unwrap();
+    ///
+    /// assert_eq!(restored, display);
+    /// ```
+    pub fn from_snapshot(snapshot: &[u8]) -> Result<Self, SnapshotError> {
+        let mut buffer = Self::new();
+        buffer.restore_snapshot(snapshot)?;
+
+        Ok(buffer)
+    }
+
+    /// Replace the active area with one read from a validated snapshot.
+    fn restore_active_area(&mut self, active_area: Rectangle) {
+        self.active_area.clear();
+        self.active_area.update_from_rect(active_area);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use serde::{
+        de::{self, DeserializeSeed, SeqAccess, Visitor},
+        ser::SerializeStruct,
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    /// Serializes the pixel data as a byte string, for formats that support them.
+    struct Bytes<'a>(&'a [u8]);
+
+    impl Serialize for Bytes<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    /// Serialized as the snapshot header fields followed by the pixel data.
+    impl<const W: u32, const H: u32, const N: usize, C> Serialize for PackedBuffer<W, H, N, C>
+    where
+        C: PixelColor + IntoStorage<Storage = u8>,
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("PackedBuffer", 2)?;
+
+            state.serialize_field("header", &Header::new(self))?;
+            state.serialize_field("data", &Bytes(&self.buf))?;
+
+            state.end()
+        }
+    }
+
+    /// Reads pixel data straight into a buffer without allocating.
+    struct Data<'a, const N: usize>(&'a mut [u8; N]);
+
+    impl<'de, const N: usize> DeserializeSeed<'de> for Data<'_, N> {
+        type Value = ();
+
+        fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+            deserializer.deserialize_bytes(self)
+        }
+    }
+
+    impl<'de, const N: usize> Visitor<'de> for Data<'_, N> {
+        type Value = ();
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} bytes of pixel data", N)
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<(), E> {
+            if bytes.len() != N {
+                return Err(E::custom(SnapshotError::LengthMismatch(bytes.len())));
+            }
+
+            self.0.copy_from_slice(bytes);
+
+            Ok(())
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+            let mut len = 0;
+
+            while let Some(byte) = seq.next_element::<u8>()? {
+                if let Some(dest) = self.0.get_mut(len) {
+                    *dest = byte;
+                }
+
+                len += 1;
+            }
+
+            if len != N {
+                return Err(de::Error::custom(SnapshotError::LengthMismatch(len)));
+            }
+
+            Ok(())
+        }
+    }
+
+    #[derive(Deserialize)]
+    #[serde(field_identifier, rename_all = "lowercase")]
+    enum Field {
+        Header,
+        Data,
+    }
+
+    struct BufferVisitor<const W: u32, const H: u32, const N: usize, C>(
+        core::marker::PhantomData<C>,
+    );
+
+    impl<'de, const W: u32, const H: u32, const N: usize, C> Visitor<'de> for BufferVisitor<W, H, N, C>
+    where
+        C: PixelColor + IntoStorage<Storage = u8>,
+    {
+        type Value = PackedBuffer<W, H, N, C>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a PackedBuffer snapshot")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let header: Header = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+
+            // Check the header first so mismatched buffers aren't reported as bad data lengths
+            let active_area = header.validate::<W, H, N, C>().map_err(de::Error::custom)?;
+
+            let mut buffer = PackedBuffer::new();
+
+            seq.next_element_seed(Data(&mut buffer.buf))?
+                .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+            buffer.restore_active_area(active_area);
+
+            Ok(buffer)
+        }
+
+        fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut buffer = PackedBuffer::new();
+            let mut active_area = None;
+            let mut has_data = false;
+
+            while let Some(field) = map.next_key()? {
+                match field {
+                    Field::Header => {
+                        let header: Header = map.next_value()?;
+
+                        active_area =
+                            Some(header.validate::<W, H, N, C>().map_err(de::Error::custom)?);
+                    }
+                    Field::Data => {
+                        map.next_value_seed(Data(&mut buffer.buf))?;
+                        has_data = true;
+                    }
+                }
+            }
+
+            let active_area = active_area.ok_or_else(|| de::Error::missing_field("header"))?;
+
+            if !has_data {
+                return Err(de::Error::missing_field("data"));
+            }
+
+            buffer.restore_active_area(active_area);
+
+            Ok(buffer)
+        }
+    }
+
+    impl<'de, const W: u32, const H: u32, const N: usize, C> Deserialize<'de>
+        for PackedBuffer<W, H, N, C>
+    where
+        C: PixelColor + IntoStorage<Storage = u8>,
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_struct(
+                "PackedBuffer",
+                &["header", "data"],
+                BufferVisitor(core::marker::PhantomData),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer_size, SliceSink};
+    use embedded_graphics::pixelcolor::{BinaryColor, Gray2};
+
+    type Display = PackedBuffer<13, 11, { buffer_size::<Gray2>(13, 11) }, Gray2>;
+
+    fn display() -> Display {
+        Display::from_art(
+            "
+            0000000000000
+            0123012301230
+            0000000000000
+            0000330000000
+            0000330000000
+            0000000000000
+            0000000000000
+            0000000000000
+            0000000000000
+            0000000000002
+            0000000000000
+            ",
+        )
+        .unwrap()
+    }
+
+    fn snapshot(display: &Display) -> [u8; Display::SNAPSHOT_LEN] {
+        let mut buf = [0u8; Display::SNAPSHOT_LEN];
+        let mut sink = SliceSink::new(&mut buf);
+
+        display.write_snapshot(&mut sink).unwrap();
+        assert_eq!(sink.written().len(), Display::SNAPSHOT_LEN);
+
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        // Untouched active area
+        assert_eq!(Display::from_snapshot(&snapshot(&display())), Ok(display()));
+
+        let mut display = display();
+
+        display.set_pixel(Point::new(3, 2), Gray2::new(1));
+        display.set_pixel(Point::new(7, 8), Gray2::new(2));
+
+        let snapshot = snapshot(&display);
+
+        assert_eq!(&snapshot[..6], b"PDB\x01\x02\x00");
+
+        let restored = Display::from_snapshot(&snapshot).unwrap();
+
+        assert!(restored.diff(&display).is_empty());
+        assert_eq!(
+            restored.active_area(),
+            Rectangle::with_corners(Point::new(3, 2), Point::new(7, 8))
+        );
+        assert_eq!(restored, display);
+    }
+
+    #[test]
+    fn rejects_mismatches() {
+        let snapshot = snapshot(&display());
+
+        let mut other = PackedBuffer::<11, 13, { buffer_size::<Gray2>(11, 13) }, Gray2>::new();
+        assert_eq!(
+            other.restore_snapshot(&snapshot),
+            Err(SnapshotError::SizeMismatch {
+                width: 13,
+                height: 11
+            })
+        );
+
+        assert_eq!(
+            PackedBuffer::<13, 11, 26, BinaryColor>::from_snapshot(&snapshot),
+            Err(SnapshotError::DepthMismatch(2))
+        );
+
+        assert_eq!(
+            Display::from_snapshot(&snapshot[..snapshot.len() - 1]),
+            Err(SnapshotError::LengthMismatch(
+                Display::SNAPSHOT_LEN - HEADER_LEN - 1
+            ))
+        );
+        assert_eq!(
+            Display::from_snapshot(&snapshot[..10]),
+            Err(SnapshotError::InvalidHeader)
+        );
+
+        let modified = |offset: usize, value: u8| {
+            let mut snapshot = snapshot;
+            snapshot[offset] = value;
+
+            Display::from_snapshot(&snapshot)
+        };
+
+        assert_eq!(modified(0, b'X'), Err(SnapshotError::InvalidHeader));
+        assert_eq!(modified(3, 2), Err(SnapshotError::UnsupportedVersion(2)));
+        assert_eq!(
+            modified(5, 1),
+            Err(SnapshotError::LayoutMismatch(Layout::Horizontal))
+        );
+        assert_eq!(modified(5, 7), Err(SnapshotError::InvalidHeader));
+        // Data length
+        assert_eq!(modified(30, 200), Err(SnapshotError::LengthMismatch(200)));
+        // Active area width
+        assert_eq!(modified(22, 14), Err(SnapshotError::InvalidActiveArea));
+    }
+
+    #[test]
+    fn failed_restore_leaves_buffer_unchanged() {
+        let mut snapshot = snapshot(&display());
+        snapshot[26] = 200;
+
+        let mut display = Display::new();
+        display.set_pixel(Point::new(1, 1), Gray2::new(3));
+
+        let before = display.buf;
+
+        assert_eq!(
+            display.restore_snapshot(&snapshot),
+            Err(SnapshotError::InvalidActiveArea)
+        );
+        assert_eq!(display.get_pixel(Point::new(1, 1)), Some(Gray2::new(3)));
+        assert_eq!(display.buf, before);
+        assert_eq!(
+            display.active_area(),
+            Rectangle::new(Point::new(1, 1), Size::new(1, 1))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let mut buffer = display();
+        buffer.set_pixel(Point::new(12, 10), Gray2::new(3));
+
+        let json = serde_json::to_string(&buffer).unwrap();
+        assert_eq!(serde_json::from_str::<Display>(&json).unwrap(), buffer);
+
+        let bytes = postcard::to_allocvec(&buffer).unwrap();
+        assert_eq!(postcard::from_bytes::<Display>(&bytes).unwrap(), buffer);
+
+        let error = serde_json::from_str::<PackedBuffer<13, 11, 26, BinaryColor>>(&json)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("snapshot has 2bpp pixels"), "{}", error);
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{mask, raster_op::RasterOp, PackedBuffer};
+use core::{convert::Infallible, fmt};
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::{IntoStorage, PixelColor},
+    primitives::Rectangle,
+    Pixel,
+};
+
+/// A draw target for a rectangular region of a [`PackedBuffer`].
+///
+/// Created by [`PackedBuffer::sub_target`]. Coordinates are relative to the top left corner of the
+/// region, and anything drawn outside the region is clipped. Unlike the generic
+/// `embedded-graphics` `Cropped` and `Clipped` adapters, solid and contiguous fills are passed to
+/// the buffer as whole rectangles, so they keep using the block based fast paths. The buffer's
+/// raster operation is applied and its active area is updated as usual.
+///
+/// ```rust
+/// use embedded_graphics_core::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle};
+/// use packed_display_buffer::PackedBuffer;
+///
+/// let mut display = PackedBuffer::<128, 64, 1024, BinaryColor>::new();
+///
+/// let mut widget = display.sub_target(&Rectangle::new(Point::new(32, 16), Size::new(20, 10)));
+///
+/// // Draw a border around the widget in its own coordinates. Only the visible part is drawn.
+/// widget.fill_solid(&Rectangle::new(Point::new(-5, 0), Size::new(40, 1)), BinaryColor::On)?;
+/// assert_eq!(widget.size(), Size::new(20, 10));
+///
+/// assert_eq!(
+///     display.active_area(),
+///     Rectangle::new(Point::new(32, 16), Size::new(20, 1))
+/// );
+/// # Ok::<(), core::convert::Infallible>(())
+/// ```
+pub struct SubTarget<'a, const W: u32, const H: u32, const N: usize, C> {
+    parent: &'a mut PackedBuffer<W, H, N, C>,
+    size: Size,
+    /// Position of the region's origin in the buffer.
+    offset: Point,
+    /// Visible part of the region in buffer coordinates.
+    clip: Rectangle,
+}
+
+impl<'a, const W: u32, const H: u32, const N: usize, C> SubTarget<'a, W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    /// Get a draw target for a region of this one, in the same way as
+    /// [`PackedBuffer::sub_target`].
+    ///
+    /// The region is given in this target's coordinates and is also clipped to this target.
+    pub fn sub_target(&mut self, area: &Rectangle) -> SubTarget<'_, W, H, N, C> {
+        let area = Rectangle::new(area.top_left + self.offset, area.size);
+
+        SubTarget {
+            parent: self.parent,
+            size: area.size,
+            offset: area.top_left,
+            clip: area.intersection(&self.clip),
+        }
+    }
+
+    /// The visible part of this target in buffer coordinates.
+    ///
+    /// This is zero sized if the region lies entirely outside the buffer.
+    pub fn visible_area(&self) -> Rectangle {
+        self.clip
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> fmt::Debug for SubTarget<'_, W, H, N, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SubTarget")
+            .field("size", &self.size)
+            .field("offset", &self.offset)
+            .field("clip", &self.clip)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> OriginDimensions for SubTarget<'_, W, H, N, C> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> DrawTarget for SubTarget<'_, W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    type Color = C;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let point = point + self.offset;
+
+            if self.clip.contains(point) {
+                self.parent.set_pixel(point, color);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = Rectangle::new(area.top_left + self.offset, area.size).intersection(&self.clip);
+
+        self.parent.fill_solid(&area, color)
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let area = Rectangle::new(area.top_left + self.offset, area.size);
+
+        self.parent.fill_rect_iter(&area, &self.clip, colors);
+
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        // Like `PackedBuffer::clear`, the raster op is ignored
+        let pattern = mask::repeat(color.into_storage(), PackedBuffer::<W, H, N, C>::SLOT_BITS);
+
+        self.parent
+            .fill_pattern(&self.clip, pattern, RasterOp::Replace);
+
+        Ok(())
+    }
+}
+
+impl<const W: u32, const H: u32, const N: usize, C> PackedBuffer<W, H, N, C>
+where
+    C: PixelColor + IntoStorage<Storage = u8>,
+{
+    /// Get a draw target for a region of the buffer, which translates coordinates so `(0, 0)` is
+    /// the top left corner of the region and clips drawing to it.
+    ///
+    /// The region may extend past the edges of the display, in which case only the part inside
+    /// the display is drawn. See [`SubTarget`] for details.
+    pub fn sub_target(&mut self, area: &Rectangle) -> SubTarget<'_, W, H, N, C> {
+        SubTarget {
+            size: area.size,
+            offset: area.top_left,
+            clip: area.intersection(&self.area),
+            parent: self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::{
+        pixelcolor::BinaryColor,
+        prelude::*,
+        primitives::{Line, PrimitiveStyle},
+    };
+
+    type Display = PackedBuffer<10, 6, 10, BinaryColor>;
+
+    fn assert_display(display: &Display, art: &str) {
+        let expected = Display::from_art(art).unwrap();
+        let diff = display.diff(&expected);
+
+        assert!(diff.is_empty(), "{}", diff);
+    }
+
+    #[test]
+    fn translate_and_clip() {
+        let mut display = Display::new();
+
+        let mut sub = display.sub_target(&Rectangle::new(Point::new(2, 1), Size::new(5, 4)));
+
+        assert_eq!(
+            sub.bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(5, 4))
+        );
+
+        Line::new(Point::new(-2, -2), Point::new(7, 7))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(&mut sub)
+            .unwrap();
+
+        sub.fill_solid(
+            &Rectangle::new(Point::new(3, -1), Size::new(10, 2)),
+            BinaryColor::On,
+        )
+        .unwrap();
+
+        sub.fill_contiguous(
+            &Rectangle::new(Point::new(-1, 3), Size::new(3, 2)),
+            [true, false, true, true, true, true].map(BinaryColor::from),
+        )
+        .unwrap();
+
+        assert_display(
+            &display,
+            "
+            ..........
+            ..#..##...
+            ...#......
+            ....#.....
+            ...#.#....
+            ..........
+            ",
+        );
+        assert_eq!(
+            display.active_area(),
+            Rectangle::new(Point::new(2, 1), Size::new(5, 4))
+        );
+    }
+
+    #[test]
+    fn clear() {
+        let mut display = Display::new();
+        display.set_raster_op(RasterOp::Xor);
+
+        let mut sub = display.sub_target(&Rectangle::new(Point::new(7, -2), Size::new(5, 4)));
+        let visible = Rectangle::new(Point::new(7, 0), Size::new(3, 2));
+
+        assert_eq!(sub.visible_area(), visible);
+
+        // The raster op is ignored, so clearing twice doesn't undo the first clear
+        sub.clear(BinaryColor::On).unwrap();
+        sub.clear(BinaryColor::On).unwrap();
+
+        assert_display(
+            &display,
+            "
+            .......###
+            .......###
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        );
+        assert_eq!(display.active_area(), visible);
+    }
+
+    #[test]
+    fn nested() {
+        let mut display = Display::new();
+
+        let mut outer = display.sub_target(&Rectangle::new(Point::new(1, 1), Size::new(6, 4)));
+        let mut inner = outer.sub_target(&Rectangle::new(Point::new(4, -1), Size::new(4, 3)));
+
+        assert_eq!(
+            inner.visible_area(),
+            Rectangle::new(Point::new(5, 1), Size::new(2, 2))
+        );
+
+        inner.clear(BinaryColor::On).unwrap();
+
+        // Inside the display but outside the outer target
+        Pixel(Point::new(0, 0), BinaryColor::On)
+            .draw(&mut inner)
+            .unwrap();
+
+        // Entirely outside the display
+        let mut hidden = display.sub_target(&Rectangle::new(Point::new(20, 0), Size::new(4, 4)));
+        assert!(hidden.visible_area().is_zero_sized());
+
+        hidden.clear(BinaryColor::On).unwrap();
+        hidden
+            .fill_contiguous(
+                &Rectangle::new(Point::zero(), Size::new(4, 4)),
+                core::iter::repeat(BinaryColor::On),
+            )
+            .unwrap();
+
+        assert_display(
+            &display,
+            "
+            ..........
+            .....##...
+            .....##...
+            ..........
+            ..........
+            ..........
+            ",
+        );
+    }
+}